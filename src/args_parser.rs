@@ -1,13 +1,40 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::board::Orientation;
+use crate::dither::Dither;
+use crate::image_import::ResizeFilter;
+use crate::palette::ColorDistance;
+use crate::planner::PixelOrder;
+use crate::transform::{parse_color_pair, parse_crop, Rotation};
+
+/// Options propres à un pattern, réglables depuis le manifest
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PatternOptions {
+    /// Nombre maximum de pixels de ce pattern placés par batch
+    pub max_pixels_per_batch: Option<usize>,
+    /// Poids du pattern face aux patterns de même priorité (1 par défaut, plus grand = plus urgent)
+    pub weight: Option<u32>,
+    /// Ordre de placement des pixels faux du pattern, le plus ancien d'abord par défaut
+    pub order: Option<PixelOrder>,
+    /// Graine de `order = "random"` (0 par défaut)
+    pub seed: Option<u64>,
+}
 
 // TODO probably should implement instead of making everything public
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct ArgSpecs {
+    pub name: String,
     pub pattern_path: String,
     pub x: i32,
     pub y: i32,
     pub priority: u32, // priority lower = higher
+    pub options: PatternOptions,
+    /// `origin = "auto"` : x et y sont trouvés sur la board au démarrage
+    #[serde(default)]
+    pub auto_origin: bool,
 }
 
 impl Ord for ArgSpecs {
@@ -23,16 +50,286 @@ impl PartialOrd for ArgSpecs {
 }
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 pub struct Args {
-    #[arg(long)]
-    pub refresh_token: String,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[arg(long, required_unless_present = "dry_run")]
+    pub refresh_token: Option<String>,
     
+    #[arg(long, required_unless_present = "dry_run")]
+    pub token: Option<String>,
+
+    /// Fetch the board and print the pixels that would be placed, without placing any.
+    /// Still contacts the server of --base-url or the config file, the live event server by default
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// With --dry-run, also write the placement plan to this JSON file
+    #[arg(long, requires = "dry_run")]
+    pub plan_output: Option<PathBuf>,
+
+    /// TOML configuration file, every other option overrides its values
     #[arg(long)]
-    pub token: String,
+    pub config: Option<PathBuf>,
 
+    /// Replaces the `patterns` list of the config file
     #[arg(long = "pattern")]
     pub patterns: Vec<String>,
+
+    /// TOML or JSON file listing the patterns, replaces the `manifest` of the config file
+    #[arg(long)]
+    pub manifest: Option<PathBuf>,
+
+    #[arg(long)]
+    pub base_url: Option<String>,
+
+    #[arg(long)]
+    pub board_endpoint: Option<String>,
+
+    #[arg(long)]
+    pub set_endpoint: Option<String>,
+
+    #[arg(long)]
+    pub max_pixels_per_batch: Option<usize>,
+
+    #[arg(long)]
+    pub batch_delay_minutes: Option<u64>,
+
+    #[arg(long)]
+    pub max_retries: Option<u32>,
+
+    #[arg(long)]
+    pub retry_delay_secs: Option<u64>,
+
+    #[arg(long)]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// JSONL file where griefed pattern pixels are logged
+    #[arg(long)]
+    pub grief_log: Option<PathBuf>,
+
+    /// How the server board matrix maps to (x, y) coordinates
+    #[arg(long, value_enum)]
+    pub orientation: Option<Orientation>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Convert a PNG/GIF/JPEG image into a pattern JSON file
+    ImportImage(ImportImageArgs),
+    /// Draw a pattern, or every pattern of a directory, to a PNG preview
+    RenderPattern(RenderPatternArgs),
+    /// Check pattern files, or the configured patterns at their origin, and report problems
+    Validate(ValidateArgs),
+    /// Write a flipped, rotated, scaled, recolored or cropped copy of a pattern
+    Transform(TransformArgs),
+    /// Write a rectangle of the live board, or of a saved snapshot, as a pattern file
+    Capture(CaptureArgs),
+    /// Find where a pattern best matches the live board or a saved snapshot
+    Locate(LocateArgs),
+    /// Report where the configured patterns overlap and export their merged target
+    Overlaps(OverlapsArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ImportImageArgs {
+    pub input: PathBuf,
+
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Resize to this width (keeps the ratio if --height is not given)
+    #[arg(long)]
+    pub width: Option<u32>,
+
+    /// Resize to this height (keeps the ratio if --width is not given)
+    #[arg(long)]
+    pub height: Option<u32>,
+
+    #[arg(long, value_enum, default_value = "nearest")]
+    pub filter: ResizeFilter,
+
+    /// Palette file in the colors.txt format, the bundled palette is used by default
+    #[arg(long)]
+    pub palette: Option<PathBuf>,
+
+    /// Pixels with a lower alpha are skipped
+    #[arg(long, default_value_t = 128)]
+    pub alpha_threshold: u8,
+
+    #[arg(long, value_enum, default_value = "none")]
+    pub dither: Dither,
+
+    /// Size of the Bayer matrix used by --dither ordered
+    #[arg(long, default_value = "4", value_parser = parse_bayer_size)]
+    pub bayer_size: usize,
+
+    /// How the nearest palette color is chosen
+    #[arg(long, value_enum, default_value = "rgb")]
+    pub distance: ColorDistance,
+
+    /// Give this weight to edge pixels (next to a transparent pixel or another color).
+    /// Weights are only stored in .json, a .txt or .rle output is refused
+    #[arg(long)]
+    pub edge_weight: Option<u32>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RenderPatternArgs {
+    #[arg(required_unless_present = "contact_sheet")]
+    pub input: Option<PathBuf>,
+
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Size in pixels of each pattern pixel
+    #[arg(long, default_value_t = 8)]
+    pub zoom: u32,
+
+    /// Draw a grid between pattern pixels
+    #[arg(long)]
+    pub grid: bool,
+
+    /// Render every pattern of this directory into a single contact sheet
+    #[arg(long, conflicts_with = "input")]
+    pub contact_sheet: Option<PathBuf>,
+
+    /// Palette file in the colors.txt format, defaults to the last board fetch then to the bundled palette
+    #[arg(long)]
+    pub palette: Option<PathBuf>,
+
+    /// Where to look for the colors of the last board fetch, defaults to snapshot_dir of the config (map)
+    #[arg(long)]
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateArgs {
+    /// Pattern files to check, defaults to the patterns of --config, --pattern and --manifest
+    pub files: Vec<PathBuf>,
+
+    /// Palette file in the colors.txt format, defaults to the last board fetch then to the bundled palette
+    #[arg(long)]
+    pub palette: Option<PathBuf>,
+
+    /// Where to look for the colors and board size of the last board fetch,
+    /// defaults to snapshot_dir of the config (map)
+    #[arg(long)]
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TransformArgs {
+    pub input: PathBuf,
+
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Keep only this rectangle of the input, given as x,y,width,height
+    #[arg(long, value_parser = parse_crop)]
+    pub crop: Option<[u32; 4]>,
+
+    /// Replace a color id by another, as FROM:TO (repeatable)
+    #[arg(long, value_parser = parse_color_pair)]
+    pub recolor: Vec<(u8, u8)>,
+
+    /// Exchange two color ids, as A:B (repeatable)
+    #[arg(long, value_parser = parse_color_pair)]
+    pub swap: Vec<(u8, u8)>,
+
+    /// Mirror left / right
+    #[arg(long)]
+    pub flip_x: bool,
+
+    /// Mirror top / bottom
+    #[arg(long)]
+    pub flip_y: bool,
+
+    /// Rotate clockwise by this many degrees
+    #[arg(long, value_enum)]
+    pub rotate: Option<Rotation>,
+
+    /// Integer upscale factor
+    #[arg(long)]
+    pub scale: Option<u32>,
+
+    /// Give this weight to edge pixels (next to an empty cell or another color), applied last.
+    /// Weights are only stored in .json, a .txt or .rle output is refused
+    #[arg(long)]
+    pub edge_weight: Option<u32>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CaptureArgs {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Read a saved board_*.txt instead of fetching the board (a directory means its latest snapshot)
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+
+    /// Color ids left out of the pattern, e.g. the background (repeatable)
+    #[arg(long)]
+    pub exclude: Vec<u8>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct LocateArgs {
+    pub pattern: PathBuf,
+
+    /// Read a saved board_*.txt instead of fetching the board (a directory means its latest snapshot)
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+
+    /// Number of candidates to print
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+
+    /// Highest share of wrong pixels for a candidate, between 0 and 1
+    #[arg(long, default_value_t = 0.2)]
+    pub tolerance: f64,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct OverlapsArgs {
+    /// Read a saved board_*.txt instead of fetching the board (a directory means its latest snapshot)
+    #[arg(long)]
+    pub snapshot: Option<PathBuf>,
+
+    /// Write the merged target of every pattern as a PNG, in board coordinates
+    #[arg(long)]
+    pub png: Option<PathBuf>,
+
+    /// Write the merged target as a pattern file (.json, .txt or .rle), in board coordinates
+    #[arg(long)]
+    pub json: Option<PathBuf>,
+
+    /// Size in pixels of each board cell in the PNG
+    #[arg(long, default_value_t = 4)]
+    pub zoom: u32,
+
+    /// Palette file in the colors.txt format (default: latest snapshot, then the bundled palette)
+    #[arg(long)]
+    pub palette: Option<PathBuf>,
+
+    /// Where to look for the colors of the last board fetch, defaults to snapshot_dir of the config (map)
+    #[arg(long)]
+    pub snapshot_dir: Option<PathBuf>,
+}
+
+/// Matrices de Bayer utiles : en dessous de 2 il n'y a pas de tramage, au-delà de 8 le bruit devient trop fin
+fn parse_bayer_size(value: &str) -> Result<usize, String> {
+    match value {
+        "2" | "4" | "8" => Ok(value.parse().expect("checked above")),
+        _ => Err(format!("expected 2, 4 or 8, got {}", value)),
+    }
 }
 
 pub fn parse_patterns(pattern: &str) -> Result<ArgSpecs, String> {
@@ -48,10 +345,18 @@ pub fn parse_patterns(pattern: &str) -> Result<ArgSpecs, String> {
     let priority = parts[3].parse::<u32>()
         .map_err(|_| format!("Invalid y priority: {}", parts[3]))?;
 
+    let name = Path::new(parts[0])
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| parts[0].to_string());
+
     Ok(ArgSpecs {
-        pattern_path: parts[0].try_into().unwrap(),
+        name,
+        pattern_path: parts[0].into(),
         x,
         y,
         priority,
+        options: PatternOptions::default(),
+        auto_origin: false,
     })
 }
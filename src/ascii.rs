@@ -0,0 +1,350 @@
+use anyhow::{Result, anyhow};
+use log::warn;
+use std::{collections::{HashMap, HashSet}, path::Path};
+
+use crate::palette::Palette;
+use crate::pattern::{Pattern, PatternFile, PatternPixel, PixelColor};
+
+// Caractères réservés aux cases vides de la grille
+const EMPTY: [char; 2] = ['.', ' '];
+// Caractères proposés quand l'initiale de la couleur est déjà prise
+const FALLBACK_CHARS: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ@%&*+=~^$0123456789";
+// Largeur maximale d'une ligne RLE quand le fichier ne donne pas de `size:`
+const MAX_RUN_LENGTH_WIDTH: usize = 4096;
+
+/// Écriture des lignes de la grille
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridEncoding {
+    /// `.txt` : un caractère par case
+    Plain,
+    /// `.rle` : des suites `<nombre><caractère>`, le nombre est omis quand il vaut 1
+    /// et les cases vides en fin de ligne ne sont pas écrites
+    RunLength,
+}
+
+impl GridEncoding {
+    /// Format texte selon l'extension, `None` pour le JSON
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "txt" => Some(GridEncoding::Plain),
+            "rle" => Some(GridEncoding::RunLength),
+            _ => None,
+        }
+    }
+}
+
+/// Un id, un nom de la palette fournie, `4|6` pour une liste ou `any`
+fn parse_color(value: &str, palette: &Palette) -> Result<PixelColor> {
+    let id = |value: &str| -> Result<u8> {
+        let value = value.trim();
+        value
+            .parse::<u8>()
+            .ok()
+            .or_else(|| palette.find_by_name(value).map(|c| c.id))
+            .ok_or_else(|| anyhow!("unknown color {:?}", value))
+    };
+    let value = value.trim();
+    if value == "any" {
+        Ok(PixelColor::Any)
+    } else if value.contains('|') {
+        Ok(PixelColor::OneOf(value.split('|').map(id).collect::<Result<_>>()?))
+    } else {
+        Ok(PixelColor::Exact(id(value)?))
+    }
+}
+
+/// `"60x40"` vers `(60, 40)`
+fn parse_size(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.trim().split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+/// Cases `(colonne, caractère)` non vides d'une ligne de la grille, colonnes à partir de 0.
+/// En RLE, les suites ne peuvent pas dépasser `max_width` cases.
+fn decode_row(row: &str, encoding: GridEncoding, line: usize, max_width: usize) -> Result<Vec<(usize, char)>> {
+    match encoding {
+        GridEncoding::Plain => Ok(row.chars().enumerate().filter(|(_, c)| !EMPTY.contains(c)).collect()),
+        GridEncoding::RunLength => {
+            let mut cells = Vec::new();
+            let mut x: usize = 0;
+            let mut count = String::new();
+            for c in row.chars() {
+                if c.is_ascii_digit() {
+                    count.push(c);
+                    continue;
+                }
+                let run = if count.is_empty() {
+                    1
+                } else {
+                    count.parse::<usize>().map_err(|_| anyhow!("line {}: invalid run length {}", line, count))?
+                };
+                let end = x
+                    .checked_add(run)
+                    .filter(|&end| end <= max_width)
+                    .ok_or_else(|| anyhow!("line {}: run length {} goes past the width of {} cells", line, run, max_width))?;
+                count.clear();
+                if !EMPTY.contains(&c) {
+                    cells.extend((x..end).map(|x| (x, c)));
+                }
+                x = end;
+            }
+            if !count.is_empty() {
+                return Err(anyhow!("line {}: run length {} without a character", line, count));
+            }
+            Ok(cells)
+        }
+    }
+}
+
+/// Lit un pattern texte :
+///
+/// ```text
+/// # commentaire
+/// size: 5x2
+/// legend:
+/// r = red
+/// w = 1
+/// m = 4|6
+/// ? = any
+/// grid:
+/// .rrr.
+/// rwmwr
+/// ```
+///
+/// `.` et l'espace sont des cases vides. Sans `size:`, la boîte du pattern est la taille de la grille.
+/// Renvoie aussi la position `(ligne, colonne)` de chaque pixel dans le fichier.
+pub fn parse(content: &str, encoding: GridEncoding) -> Result<(PatternFile, Vec<(usize, usize)>)> {
+    let palette = Palette::bundled();
+    let mut lines = content.lines().enumerate();
+    let mut legend: HashMap<char, PixelColor> = HashMap::new();
+    let mut size = None;
+    let mut in_legend = false;
+
+    // En-tête : commentaires, taille et légende, jusqu'à la ligne `grid:`
+    loop {
+        let Some((i, line)) = lines.next() else {
+            return Err(anyhow!("missing `grid:` line"));
+        };
+        let trimmed = line.trim();
+        // `# = red` ressemble à un commentaire : on le refuse plutôt que de perdre l'entrée
+        if in_legend && trimmed.strip_prefix('#').is_some_and(|rest| rest.trim_start().starts_with('=')) {
+            return Err(anyhow!("line {}: '#' starts comments and can't be a legend key", i + 1));
+        }
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        match trimmed {
+            "legend:" => in_legend = true,
+            "grid:" => break,
+            _ if !in_legend && trimmed.starts_with("size:") => {
+                size = Some(parse_size(&trimmed["size:".len()..])
+                    .ok_or_else(|| anyhow!("line {}: expected `size: <width>x<height>`", i + 1))?);
+            }
+            _ if in_legend => {
+                let (key, value) = trimmed
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("line {}: expected `<char> = <color>`", i + 1))?;
+                let mut chars = key.trim().chars();
+                let (Some(c), None) = (chars.next(), chars.next()) else {
+                    return Err(anyhow!("line {}: legend keys must be a single character, got {:?}", i + 1, key.trim()));
+                };
+                if EMPTY.contains(&c) {
+                    return Err(anyhow!("line {}: {:?} is reserved for empty cells", i + 1, c));
+                }
+                if encoding == GridEncoding::RunLength && c.is_ascii_digit() {
+                    return Err(anyhow!("line {}: digits are run lengths and can't be legend keys", i + 1));
+                }
+                let color = parse_color(value, &palette).map_err(|e| anyhow!("line {}: {}", i + 1, e))?;
+                if legend.insert(c, color).is_some() {
+                    return Err(anyhow!("line {}: {:?} is defined twice", i + 1, c));
+                }
+            }
+            _ => return Err(anyhow!("line {}: expected `size:`, `legend:` or `grid:`, got {:?}", i + 1, trimmed)),
+        }
+    }
+
+    let mut rows: Vec<(usize, &str)> = lines.collect();
+    while rows.last().is_some_and(|(_, row)| row.is_empty()) {
+        rows.pop();
+    }
+
+    let mut pixels = Vec::new();
+    let mut positions = Vec::new();
+    let mut grid_width = 0;
+    let max_width = size.map_or(MAX_RUN_LENGTH_WIDTH, |(width, _)| width as usize);
+    for (y, (i, row)) in rows.iter().enumerate() {
+        let cells = decode_row(row, encoding, i + 1, max_width)?;
+        for &(x, c) in &cells {
+            let color = legend
+                .get(&c)
+                .ok_or_else(|| anyhow!("line {}, column {}: {:?} is not in the legend", i + 1, x + 1, c))?;
+            pixels.push(PatternPixel { x: x as i32, y: y as i32, color: color.clone(), weight: None });
+            // En RLE, la colonne du fichier ne correspond pas à x : on garde le début de la ligne
+            positions.push((i + 1, if encoding == GridEncoding::Plain { x + 1 } else { 1 }));
+        }
+        let row_width = match encoding {
+            GridEncoding::Plain => row.chars().count(),
+            GridEncoding::RunLength => cells.last().map_or(0, |&(x, _)| x + 1),
+        };
+        grid_width = grid_width.max(row_width);
+    }
+
+    let (width, height) = size.unwrap_or((grid_width as u32, rows.len() as u32));
+    let file = PatternFile {
+        width: Some(width),
+        height: Some(height),
+        pattern: pixels,
+    };
+    Ok((file, positions))
+}
+
+/// Écrit le pattern au format texte, les couleurs sont nommées quand la palette fournie les connaît.
+/// Échoue quand le pattern a plus de couleurs que de caractères disponibles pour la légende.
+pub fn write(pattern: &Pattern, encoding: GridEncoding) -> Result<String> {
+    let palette = Palette::bundled();
+    let name = |id: &u8| palette.get(*id).map_or(id.to_string(), |c| c.name.clone());
+
+    // Un caractère par couleur, dans l'ordre d'apparition : l'initiale du nom si elle est libre
+    let mut legend: Vec<(char, PixelColor)> = Vec::new();
+    for p in pattern.pixels() {
+        if legend.iter().any(|(_, color)| *color == p.color) {
+            continue;
+        }
+        let preferred = match &p.color {
+            PixelColor::Exact(id) => name(id).chars().next().map(|c| c.to_ascii_lowercase()),
+            PixelColor::OneOf(_) => None,
+            PixelColor::Any => Some('?'),
+        };
+        let c = preferred
+            .into_iter()
+            .chain(preferred.map(|c| c.to_ascii_uppercase()))
+            .chain(FALLBACK_CHARS.chars())
+            .filter(|c| encoding == GridEncoding::Plain || !c.is_ascii_digit())
+            .find(|c| !EMPTY.contains(c) && !legend.iter().any(|(used, _)| used == c))
+            .ok_or_else(|| anyhow!("more than {} different colors, the legend has no character left, save as .json",
+                legend.len()))?;
+        legend.push((c, p.color.clone()));
+    }
+
+    let (width, height) = pattern.size();
+    let mut out = format!("size: {}x{}\nlegend:\n", width, height);
+    for (c, color) in &legend {
+        let value = match color {
+            PixelColor::Exact(id) => name(id),
+            PixelColor::OneOf(ids) => ids.iter().map(name).collect::<Vec<_>>().join("|"),
+            PixelColor::Any => "any".to_string(),
+        };
+        out.push_str(&format!("{} = {}\n", c, value));
+    }
+
+    let mut grid = vec![vec!['.'; width as usize]; height as usize];
+    let mut written = HashSet::new();
+    for p in pattern.pixels() {
+        if !written.insert((p.x, p.y)) {
+            warn!("Pixel ({}, {}) is duplicated, only its last color is written", p.x, p.y);
+        }
+        let (c, _) = legend.iter().find(|(_, color)| *color == p.color).expect("every color has a character");
+        grid[p.y as usize][p.x as usize] = *c;
+    }
+    out.push_str("grid:\n");
+    for row in grid {
+        match encoding {
+            GridEncoding::Plain => out.extend(row),
+            GridEncoding::RunLength => out.push_str(&encode_row(&row)),
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+fn encode_row(row: &[char]) -> String {
+    let end = row.iter().rposition(|c| !EMPTY.contains(c)).map_or(0, |i| i + 1);
+    let mut out = String::new();
+    let mut cells = row[..end].iter().peekable();
+    while let Some(&c) = cells.next() {
+        let mut run = 1;
+        while cells.next_if_eq(&&c).is_some() {
+            run += 1;
+        }
+        if run > 1 {
+            out.push_str(&run.to_string());
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn cells(pattern: &Pattern) -> BTreeMap<(i32, i32), PixelColor> {
+        pattern.pixels().map(|p| ((p.x, p.y), p.color.clone())).collect()
+    }
+
+    #[test]
+    fn parses_legend_and_grid() {
+        let content = "# test\nlegend:\nr = red\nw = 1\nm = 4|red\n? = any\ngrid:\n.rr.\nwm?.\n";
+        let (file, positions) = parse(content, GridEncoding::Plain).unwrap();
+        let pattern = Pattern::from(file);
+        assert_eq!(pattern.size(), (4, 2));
+        assert_eq!(cells(&pattern), BTreeMap::from([
+            ((1, 0), PixelColor::Exact(6)),
+            ((2, 0), PixelColor::Exact(6)),
+            ((0, 1), PixelColor::Exact(1)),
+            ((1, 1), PixelColor::OneOf(vec![4, 6])),
+            ((2, 1), PixelColor::Any),
+        ]));
+        assert_eq!(positions[0], (8, 2));
+
+        assert!(parse("legend:\nr = red\ngrid:\nrx\n", GridEncoding::Plain).is_err());
+        assert!(parse("legend:\n. = red\ngrid:\n", GridEncoding::Plain).is_err());
+        let error = parse("legend:\n# = red\n# not a key\ngrid:\n#\n", GridEncoding::Plain).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: '#' starts comments and can't be a legend key");
+    }
+
+    #[test]
+    fn run_length_rows() {
+        let content = "size: 8x3\nlegend:\nr = red\nw = white\ngrid:\n3.2rw\n\n8r\n";
+        let pattern = Pattern::from(parse(content, GridEncoding::RunLength).unwrap().0);
+        assert_eq!(pattern.size(), (8, 3));
+        assert_eq!(pattern.pixels().count(), 11);
+        assert_eq!(encode_row(&['.', '.', '.', 'r', 'r', 'w', '.', '.']), "3.2rw");
+        assert!(parse("legend:\nr = red\ngrid:\n3\n", GridEncoding::RunLength).is_err());
+    }
+
+    #[test]
+    fn run_lengths_are_bounded_by_the_width() {
+        let error = |content: &str| parse(content, GridEncoding::RunLength).unwrap_err().to_string();
+        assert_eq!(error("size: 4x1\nlegend:\nr = red\ngrid:\n2.3r\n"),
+            "line 5: run length 3 goes past the width of 4 cells");
+        assert!(error("legend:\nr = red\ngrid:\n999999999999r\n").contains("goes past the width"));
+        assert!(error("legend:\nr = red\ngrid:\n99999999999999999999999r\n").contains("invalid run length"));
+        let pattern = Pattern::from(parse("size: 4x1\nlegend:\nr = red\ngrid:\n4r\n", GridEncoding::RunLength).unwrap().0);
+        assert_eq!(pattern.pixels().count(), 4);
+    }
+
+    #[test]
+    fn round_trips_with_json() {
+        let pattern = Pattern::load(concat!(env!("CARGO_MANIFEST_DIR"), "/pattern/core.json")).unwrap();
+        for encoding in [GridEncoding::Plain, GridEncoding::RunLength] {
+            let (file, _) = parse(&write(&pattern, encoding).unwrap(), encoding).unwrap();
+            let back = Pattern::from(file);
+            assert_eq!(back.size(), pattern.size());
+            assert_eq!(cells(&back), cells(&pattern));
+        }
+    }
+
+    #[test]
+    fn too_many_colors_for_the_legend() {
+        let pixels = (0..80)
+            .map(|x| PatternPixel { x, y: 0, color: PixelColor::Exact(x as u8 + 1), weight: None })
+            .collect();
+        let pattern = Pattern::new(80, 1, pixels);
+        for encoding in [GridEncoding::Plain, GridEncoding::RunLength] {
+            let error = write(&pattern, encoding).unwrap_err().to_string();
+            assert!(error.contains("the legend has no character left"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,413 @@
+// Serveur ftplace local pour tester le client hors des heures d'event.
+//
+// Expose `/api/get?type=board` et `/api/set` avec les mêmes formats JSON que
+// le vrai serveur, garde la board en mémoire, applique un cooldown par pixel
+// et permet de scripter des erreurs (502, 426, "Too early", ...).
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use clap::Parser;
+use env_logger::Builder;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{info, debug, LevelFilter};
+use serde::{Deserialize, Serialize};
+
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+#[path = "../colors_file.rs"]
+mod colors_file;
+
+const BUNDLED_COLORS: &str = include_str!("../../colors.txt");
+
+#[derive(Parser, Debug)]
+#[command(about = "Local mock of the ftplace server for offline testing")]
+struct Args {
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr,
+
+    #[arg(long, default_value_t = 250)]
+    width: usize,
+
+    #[arg(long, default_value_t = 250)]
+    height: usize,
+
+    /// Number of pixels a user can place before hitting the cooldown
+    #[arg(long, default_value_t = 10)]
+    pixels: usize,
+
+    /// Cooldown of each pixel slot, in seconds
+    #[arg(long, default_value_t = 31 * 60)]
+    cooldown_secs: i64,
+
+    /// Scripted answers for the next board requests, e.g. "502,502,500"
+    #[arg(long, value_parser = parse_script, default_value = "")]
+    board_script: VecDeque<Scripted>,
+
+    /// Scripted answers for the next set requests, e.g. "426,too-early,502"
+    #[arg(long, value_parser = parse_script, default_value = "")]
+    set_script: VecDeque<Scripted>,
+
+    /// Ask for a token refresh (426) every N successful placements, 0 to disable
+    #[arg(long, default_value_t = 0)]
+    refresh_every: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Scripted {
+    BadGateway,
+    Refresh,
+    TooEarly,
+    Status(u16),
+}
+
+fn parse_script(script: &str) -> Result<VecDeque<Scripted>, String> {
+    script
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| match s {
+            "502" => Ok(Scripted::BadGateway),
+            "426" => Ok(Scripted::Refresh),
+            "too-early" => Ok(Scripted::TooEarly),
+            other => other.parse::<u16>()
+                .ok()
+                .filter(|code| StatusCode::from_u16(*code).is_ok())
+                .map(Scripted::Status)
+                .ok_or_else(|| format!("Invalid scripted failure: {}", other)),
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct MockColor {
+    id: u8,
+    name: String,
+    red: u8,
+    green: u8,
+    blue: u8,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct MockPixel {
+    username: String,
+    color_id: u8,
+    set_time: String,
+}
+
+#[derive(Serialize, Debug)]
+struct BoardResponse<'a> {
+    colors: &'a [MockColor],
+    #[serde(rename = "type")]
+    response_type: &'static str,
+    board: &'a [Vec<MockPixel>],
+}
+
+#[derive(Serialize, Debug)]
+struct TimerResponse {
+    timers: Vec<String>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlacePixelRequest {
+    x: i32,
+    y: i32,
+    color: String,
+}
+
+struct MockState {
+    colors: Vec<MockColor>,
+    // Stocké comme le vrai serveur : board[x][y]
+    board: Vec<Vec<MockPixel>>,
+    timers: Vec<DateTime<Utc>>,
+    cooldown: ChronoDuration,
+    board_script: VecDeque<Scripted>,
+    set_script: VecDeque<Scripted>,
+    refresh_every: u32,
+    placed_since_refresh: u32,
+    token_generation: u32,
+    // None tant qu'aucun refresh n'a été demandé : le premier token est accepté tel quel
+    token: Option<String>,
+}
+
+fn parse_colors(content: &str) -> Result<Vec<MockColor>> {
+    Ok(colors_file::parse(content)?
+        .into_iter()
+        .map(|line| MockColor {
+            id: line.id,
+            name: line.name,
+            red: line.rgb[0],
+            green: line.rgb[1],
+            blue: line.rgb[2],
+        })
+        .collect())
+}
+
+impl MockState {
+    fn new(args: &Args) -> Result<Self> {
+        let mut colors = parse_colors(BUNDLED_COLORS)?;
+        colors.sort_by_key(|c| c.id);
+        let background = colors.first().ok_or_else(|| anyhow!("Empty palette"))?.id;
+        let epoch = DateTime::<Utc>::UNIX_EPOCH.to_rfc3339();
+
+        Ok(MockState {
+            colors,
+            board: vec![vec![MockPixel {
+                username: String::new(),
+                color_id: background,
+                set_time: epoch,
+            }; args.height]; args.width],
+            timers: vec![DateTime::<Utc>::UNIX_EPOCH; args.pixels],
+            cooldown: ChronoDuration::seconds(args.cooldown_secs),
+            board_script: args.board_script.clone(),
+            set_script: args.set_script.clone(),
+            refresh_every: args.refresh_every,
+            placed_since_refresh: 0,
+            token_generation: 0,
+            token: None,
+        })
+    }
+
+    fn pending_timers(&self, now: DateTime<Utc>) -> Vec<String> {
+        self.timers
+            .iter()
+            .filter(|t| **t > now)
+            .map(|t| t.to_rfc3339())
+            .collect()
+    }
+
+    fn refresh_response(&mut self) -> Response<Body> {
+        self.token_generation += 1;
+        let token = format!("mock-token-{}", self.token_generation);
+        let refresh = format!("mock-refresh-{}", self.token_generation);
+        self.token = Some(token.clone());
+        self.placed_since_refresh = 0;
+        info!("Asking client to refresh its tokens (generation {})", self.token_generation);
+
+        Response::builder()
+            .status(StatusCode::UPGRADE_REQUIRED)
+            .header("set-cookie", format!("token={}; Path=/; HttpOnly", token))
+            .header("set-cookie", format!("refresh={}; Path=/; HttpOnly", refresh))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    fn too_early_response(&self, now: DateTime<Utc>) -> Response<Body> {
+        json_response(StatusCode::from_u16(425).unwrap(), &TimerResponse {
+            timers: self.pending_timers(now),
+            message: Some("Too early".to_string()),
+        })
+    }
+
+    fn scripted_response(&mut self, scripted: Scripted) -> Response<Body> {
+        let now = Utc::now();
+        match scripted {
+            Scripted::BadGateway => text_response(StatusCode::BAD_GATEWAY, "Bad Gateway"),
+            Scripted::Refresh => self.refresh_response(),
+            Scripted::TooEarly => self.too_early_response(now),
+            Scripted::Status(code) => {
+                let status = StatusCode::from_u16(code).unwrap();
+                text_response(status, status.canonical_reason().unwrap_or("Scripted failure"))
+            }
+        }
+    }
+
+    fn get_board(&mut self) -> Response<Body> {
+        if let Some(scripted) = self.board_script.pop_front() {
+            info!("Board request: scripted {:?}", scripted);
+            return self.scripted_response(scripted);
+        }
+
+        json_response(StatusCode::OK, &BoardResponse {
+            colors: &self.colors,
+            response_type: "board",
+            board: &self.board,
+        })
+    }
+
+    fn set_pixel(&mut self, cookies: Option<&str>, body: &[u8]) -> Response<Body> {
+        if let Some(scripted) = self.set_script.pop_front() {
+            info!("Set request: scripted {:?}", scripted);
+            return self.scripted_response(scripted);
+        }
+
+        let token = cookies
+            .unwrap_or_default()
+            .split(';')
+            .filter_map(|c| c.trim().strip_prefix("token="))
+            .next();
+        let username = match (token, &self.token) {
+            (None, _) => return text_response(StatusCode::UNAUTHORIZED, "Missing token"),
+            (Some(sent), Some(expected)) if sent != expected => {
+                return text_response(StatusCode::UNAUTHORIZED, "Invalid token");
+            }
+            (Some(sent), _) => sent.to_string(),
+        };
+
+        if self.refresh_every > 0 && self.placed_since_refresh >= self.refresh_every {
+            return self.refresh_response();
+        }
+
+        let request: PlacePixelRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return text_response(StatusCode::BAD_REQUEST, &format!("Invalid body: {}", e)),
+        };
+
+        let color_id = match request.color.parse::<u8>() {
+            Ok(id) if self.colors.iter().any(|c| c.id == id) => id,
+            _ => return text_response(StatusCode::BAD_REQUEST, "Invalid color"),
+        };
+
+        let in_bounds = request.x >= 0
+            && request.y >= 0
+            && (request.x as usize) < self.board.len()
+            && (request.y as usize) < self.board[0].len();
+        if !in_bounds {
+            return text_response(StatusCode::BAD_REQUEST, "Out of bounds");
+        }
+
+        let now = Utc::now();
+        let slot = match self.timers.iter().position(|t| *t <= now) {
+            Some(slot) => slot,
+            None => {
+                debug!("No free pixel slot, answering Too early");
+                return self.too_early_response(now);
+            }
+        };
+        self.timers[slot] = now + self.cooldown;
+        self.placed_since_refresh += 1;
+
+        self.board[request.x as usize][request.y as usize] = MockPixel {
+            username,
+            color_id,
+            set_time: now.to_rfc3339(),
+        };
+        info!("Pixel ({}, {}) set to color {}", request.x, request.y, color_id);
+
+        json_response(StatusCode::OK, &TimerResponse {
+            timers: self.pending_timers(now),
+            message: None,
+        })
+    }
+}
+
+fn text_response(status: StatusCode, text: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(text.to_string()))
+        .unwrap()
+}
+
+fn json_response<T: Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_vec(value).unwrap()))
+        .unwrap()
+}
+
+async fn handle(state: Arc<Mutex<MockState>>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let cookies = request.headers()
+        .get("cookie")
+        .and_then(|c| c.to_str().ok())
+        .map(str::to_string);
+    debug!("{} {}?{}", method, path, query);
+
+    let response = match (method, path.as_str()) {
+        (Method::GET, "/api/get") if query.split('&').any(|q| q == "type=board") => {
+            state.lock().unwrap().get_board()
+        }
+        (Method::POST, "/api/set") => match hyper::body::to_bytes(request.into_body()).await {
+            Ok(body) => state.lock().unwrap().set_pixel(cookies.as_deref(), &body),
+            Err(e) => text_response(StatusCode::BAD_REQUEST, &e.to_string()),
+        },
+        _ => text_response(StatusCode::NOT_FOUND, "Not found"),
+    };
+    Ok(response)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    Builder::new()
+        .filter_level(LevelFilter::Info)
+        .format_timestamp_millis()
+        .init();
+
+    let args = Args::parse();
+    let state = Arc::new(Mutex::new(MockState::new(&args)?));
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+        }
+    });
+
+    info!("Mock ftplace server listening on http://{} ({}x{} board, {} pixels every {}s)",
+        args.listen, args.width, args.height, args.pixels, args.cooldown_secs);
+    Server::bind(&args.listen).serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock(args: &[&str]) -> MockState {
+        let args = Args::parse_from([&["mock_server", "--width", "4", "--height", "3"], args].concat());
+        MockState::new(&args).unwrap()
+    }
+
+    fn set(state: &mut MockState, token: Option<&str>, x: i32) -> StatusCode {
+        let cookies = token.map(|token| format!("refresh=r; token={}", token));
+        let body = format!(r#"{{"x": {}, "y": 1, "color": "2"}}"#, x);
+        state.set_pixel(cookies.as_deref(), body.as_bytes()).status()
+    }
+
+    #[test]
+    fn parses_scripts() {
+        use Scripted::*;
+        assert_eq!(parse_script("502, too-early,426,418").unwrap(), [BadGateway, TooEarly, Refresh, Status(418)]);
+        assert!(parse_script("").unwrap().is_empty());
+        assert!(parse_script("502,teapot").is_err());
+        assert!(parse_script("1000").is_err());
+    }
+
+    #[test]
+    fn set_pixel_checks_token_and_cooldown() {
+        let mut state = mock(&["--pixels", "2"]);
+        assert_eq!(set(&mut state, None, 0), StatusCode::UNAUTHORIZED);
+        assert_eq!(set(&mut state, Some("alice"), 0), StatusCode::OK);
+        assert_eq!(set(&mut state, Some("alice"), 1), StatusCode::OK);
+        // Les deux slots sont en cooldown
+        assert_eq!(set(&mut state, Some("alice"), 2).as_u16(), 425);
+        assert_eq!(state.board[0][1].username, "alice");
+        assert_eq!(state.board[0][1].color_id, 2);
+        assert_eq!(state.board[2][1].color_id, 1);
+        assert_eq!(set(&mut state, Some("alice"), 9), StatusCode::BAD_REQUEST);
+
+        // Sans cooldown, le même slot se libère tout de suite
+        let mut state = mock(&["--pixels", "1", "--cooldown-secs", "0"]);
+        for x in 0..3 {
+            assert_eq!(set(&mut state, Some("alice"), x), StatusCode::OK);
+        }
+    }
+
+    #[test]
+    fn refresh_replaces_the_token() {
+        let mut state = mock(&["--refresh-every", "1"]);
+        assert_eq!(set(&mut state, Some("first"), 0), StatusCode::OK);
+        assert_eq!(set(&mut state, Some("first"), 1), StatusCode::UPGRADE_REQUIRED);
+        assert_eq!(set(&mut state, Some("first"), 1), StatusCode::UNAUTHORIZED);
+        assert_eq!(set(&mut state, Some("mock-token-1"), 1), StatusCode::OK);
+    }
+}
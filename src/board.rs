@@ -0,0 +1,449 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use image::{ImageBuffer, Rgb, RgbImage};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::config::latest_snapshot_file;
+use crate::place_client::Color;
+
+/// Transformation appliquée à la matrice du serveur pour obtenir la board en `(x, y)`.
+///
+/// Les noms décrivent ce qu'on fait à la matrice lue telle quelle (ligne `i` = `y`, colonne `j` = `x`).
+/// Le serveur ftplace renvoie `board[x][y]`, d'où `transpose` par défaut.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Orientation {
+    None,
+    #[default]
+    Transpose,
+    /// Rotation de 90° dans le sens horaire
+    Rotate90,
+    Rotate180,
+    /// Rotation de 90° dans le sens anti-horaire
+    Rotate270,
+    /// Miroir gauche / droite
+    FlipX,
+    /// Miroir haut / bas
+    FlipY,
+}
+
+impl Orientation {
+    pub const ALL: [Orientation; 7] = [
+        Orientation::None,
+        Orientation::Transpose,
+        Orientation::Rotate90,
+        Orientation::Rotate180,
+        Orientation::Rotate270,
+        Orientation::FlipX,
+        Orientation::FlipY,
+    ];
+
+    /// Dimensions `(width, height)` de la board pour une matrice de `rows` lignes de `cols` pixels
+    pub fn board_size(self, rows: usize, cols: usize) -> (usize, usize) {
+        match self {
+            Orientation::None | Orientation::Rotate180 | Orientation::FlipX | Orientation::FlipY => (cols, rows),
+            Orientation::Transpose | Orientation::Rotate90 | Orientation::Rotate270 => (rows, cols),
+        }
+    }
+
+    /// Position `(ligne, colonne)` dans la matrice du serveur du pixel `(x, y)` de la board
+    pub fn source_index(self, rows: usize, cols: usize, x: usize, y: usize) -> (usize, usize) {
+        match self {
+            Orientation::None => (y, x),
+            Orientation::Transpose => (x, y),
+            Orientation::Rotate90 => (rows - 1 - x, y),
+            Orientation::Rotate180 => (rows - 1 - y, cols - 1 - x),
+            Orientation::Rotate270 => (x, cols - 1 - y),
+            Orientation::FlipX => (y, cols - 1 - x),
+            Orientation::FlipY => (rows - 1 - y, x),
+        }
+    }
+
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// Qui a posé un pixel et quand, tel que renvoyé par le serveur
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PixelInfo {
+    pub username: Option<String>,
+    pub set_time: Option<DateTime<Utc>>,
+}
+
+impl PixelInfo {
+    pub fn new(username: &str, set_time: &str) -> Self {
+        PixelInfo {
+            username: Some(username.trim())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string),
+            set_time: parse_set_time(set_time),
+        }
+    }
+
+    pub fn username_or_unknown(&self) -> &str {
+        self.username.as_deref().unwrap_or("<unknown>")
+    }
+}
+
+/// `set_time` est en RFC 3339, avec un repli sur le format SQL sans fuseau (considéré UTC)
+fn parse_set_time(set_time: &str) -> Option<DateTime<Utc>> {
+    let set_time = set_time.trim();
+    DateTime::parse_from_rfc3339(set_time)
+        .map(|time| time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(set_time, "%Y-%m-%d %H:%M:%S%.f")
+                .ok()
+                .map(|time| time.and_utc())
+        })
+}
+
+/// État de la board avec les dimensions renvoyées par le serveur.
+///
+/// Les pixels sont stockés ligne par ligne dans un seul `Vec` (index `y * width + x`),
+/// avec à côté l'auteur et la date de chaque pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Board {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+    info: Vec<PixelInfo>,
+}
+
+/// Un pixel dont la couleur diffère entre deux boards
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelChange {
+    pub x: usize,
+    pub y: usize,
+    pub before: u8,
+    pub after: u8,
+    /// Auteur et date du pixel dans la nouvelle board
+    pub info: PixelInfo,
+}
+
+impl Board {
+    /// Construit la board depuis une matrice serveur de `rows` lignes de `cols` pixels.
+    ///
+    /// `cell(i, j)` renvoie la couleur de la ligne `i`, colonne `j` de la matrice.
+    #[cfg(test)]
+    pub fn from_matrix<F>(rows: usize, cols: usize, orientation: Orientation, cell: F) -> Self
+    where
+        F: Fn(usize, usize) -> u8,
+    {
+        Board::from_matrix_with_info(rows, cols, orientation, |i, j| (cell(i, j), PixelInfo::default()))
+    }
+
+    /// Comme `from_matrix`, en gardant l'auteur et la date de chaque pixel
+    pub fn from_matrix_with_info<F>(rows: usize, cols: usize, orientation: Orientation, cell: F) -> Self
+    where
+        F: Fn(usize, usize) -> (u8, PixelInfo),
+    {
+        let (width, height) = orientation.board_size(rows, cols);
+        let mut cells = Vec::with_capacity(width * height);
+        let mut info = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (i, j) = orientation.source_index(rows, cols, x, y);
+                let (color, pixel_info) = cell(i, j);
+                cells.push(color);
+                info.push(pixel_info);
+            }
+        }
+        Board { width, height, cells, info }
+    }
+
+    /// Relit un `board_<timestamp>.txt` sauvegardé par le client : une ligne par `y`, les couleurs séparées par des espaces.
+    /// Les auteurs et dates ne sont pas sauvegardés et restent vides.
+    pub fn parse_snapshot(content: &str) -> Result<Self> {
+        let rows: Vec<Vec<u8>> = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                line.split_whitespace()
+                    .map(|id| id.parse::<u8>().map_err(|_| anyhow!("line {}: invalid color id {:?}", i + 1, id)))
+                    .collect()
+            })
+            .collect::<Result<_>>()?;
+
+        let width = rows.first().map_or(0, Vec::len);
+        if let Some(i) = rows.iter().position(|row| row.len() != width) {
+            return Err(anyhow!("line {}: expected {} colors, got {}", i + 1, width, rows[i].len()));
+        }
+        Ok(Board::from_matrix_with_info(rows.len(), width, Orientation::None, |i, j| (rows[i][j], PixelInfo::default())))
+    }
+
+    /// Snapshot `board_<timestamp>.txt` donné, ou le plus récent si `path` est un dossier
+    pub fn load_snapshot(path: &Path) -> Result<Self> {
+        let path = if path.is_dir() {
+            latest_snapshot_file(path, "board")
+                .ok_or_else(|| anyhow!("No board snapshot in {}", path.display()))?
+        } else {
+            path.to_path_buf()
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Couldn't read snapshot {}: {}", path.display(), e))?;
+        Board::parse_snapshot(&content)
+            .map_err(|e| anyhow!("Invalid snapshot {}: {}", path.display(), e))
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if self.contains(x, y) {
+            Some(y as usize * self.width + x as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<u8> {
+        self.index(x, y).map(|i| self.cells[i])
+    }
+
+    pub fn info(&self, x: i32, y: i32) -> Option<&PixelInfo> {
+        self.index(x, y).map(|i| &self.info[i])
+    }
+
+    /// Depuis combien de temps le pixel a sa couleur actuelle, si le serveur a donné sa date
+    pub fn age(&self, x: i32, y: i32, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        self.info(x, y)
+            .and_then(|info| info.set_time)
+            .map(|set_time| now.signed_duration_since(set_time))
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        // chunks(0) panique ; une board de largeur 0 n'a de toute façon aucune case
+        self.cells.chunks(self.width.max(1))
+    }
+
+    /// Orientations sous lesquelles le pixel `(x, y)` aurait la couleur `color`, pour une board
+    /// construite avec `orientation` : la matrice du serveur est reconstituée depuis la board
+    pub fn orientations_matching(&self, orientation: Orientation, x: i32, y: i32, color: u8) -> Vec<Orientation> {
+        // board_size échange ou garde les deux dimensions, il est donc son propre inverse
+        let (cols, rows) = orientation.board_size(self.height, self.width);
+        let mut matrix = vec![0; rows * cols];
+        for (bx, by, cell) in self.iter() {
+            let (i, j) = orientation.source_index(rows, cols, bx, by);
+            matrix[i * cols + j] = cell;
+        }
+
+        Orientation::ALL
+            .into_iter()
+            .filter(|candidate| {
+                let (width, height) = candidate.board_size(rows, cols);
+                if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                    return false;
+                }
+                let (i, j) = candidate.source_index(rows, cols, x as usize, y as usize);
+                matrix[i * cols + j] == color
+            })
+            .collect()
+    }
+
+    /// Tous les pixels sous la forme `(x, y, color)`, ligne par ligne
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        let width = self.width;
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(i, &color)| (i % width, i / width, color))
+    }
+
+    /// Copie du rectangle demandé, réduit à la partie qui se trouve sur la board
+    /// (une board vide si le coin est en dehors)
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Board {
+        if x >= self.width || y >= self.height {
+            return Board { width: 0, height: 0, cells: Vec::new(), info: Vec::new() };
+        }
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+        let (width, height) = (x_end - x, y_end - y);
+
+        let mut cells = Vec::with_capacity(width * height);
+        let mut info = Vec::with_capacity(width * height);
+        for row in y..y_end {
+            let start = row * self.width;
+            cells.extend_from_slice(&self.cells[start + x..start + x_end]);
+            info.extend_from_slice(&self.info[start + x..start + x_end]);
+        }
+        Board { width, height, cells, info }
+    }
+
+    /// Pixels qui ont changé entre `self` et `other`, sur la zone commune aux deux boards
+    pub fn diff(&self, other: &Board) -> Vec<PixelChange> {
+        let width = self.width.min(other.width);
+        let height = self.height.min(other.height);
+        let mut changes = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                let before = self.cells[y * self.width + x];
+                let after = other.cells[y * other.width + x];
+                if before != after {
+                    changes.push(PixelChange {
+                        x,
+                        y,
+                        before,
+                        after,
+                        info: other.info[y * other.width + x].clone(),
+                    });
+                }
+            }
+        }
+        changes
+    }
+
+    /// Image de la board, les couleurs inconnues restent noires
+    pub fn render(&self, colors: &HashMap<u8, Color>) -> RgbImage {
+        let mut img = ImageBuffer::new(self.width as u32, self.height as u32);
+        for (x, y, color_id) in self.iter() {
+            if let Some(color) = colors.get(&color_id) {
+                img.put_pixel(x as u32, y as u32, Rgb([color.red, color.green, color.blue]));
+            }
+        }
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Matrice asymétrique de 2 lignes et 3 colonnes, telle que renvoyée par le serveur
+    const MATRIX: [[u8; 3]; 2] = [
+        [1, 2, 3],
+        [4, 5, 6],
+    ];
+
+    fn oriented(orientation: Orientation) -> Vec<Vec<u8>> {
+        Board::from_matrix(2, 3, orientation, |i, j| MATRIX[i][j])
+            .rows()
+            .map(|row| row.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn none_keeps_the_matrix() {
+        assert_eq!(oriented(Orientation::None), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        assert_eq!(oriented(Orientation::Transpose), vec![vec![1, 4], vec![2, 5], vec![3, 6]]);
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(oriented(Orientation::Rotate90), vec![vec![4, 1], vec![5, 2], vec![6, 3]]);
+        assert_eq!(oriented(Orientation::Rotate180), vec![vec![6, 5, 4], vec![3, 2, 1]]);
+        assert_eq!(oriented(Orientation::Rotate270), vec![vec![3, 6], vec![2, 5], vec![1, 4]]);
+    }
+
+    #[test]
+    fn flips() {
+        assert_eq!(oriented(Orientation::FlipX), vec![vec![3, 2, 1], vec![6, 5, 4]]);
+        assert_eq!(oriented(Orientation::FlipY), vec![vec![4, 5, 6], vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn transpose_matches_legacy_rotate_then_mirror() {
+        // Ancienne reconstruction de get_board : rotation puis miroir sur une board carrée
+        const SIZE: usize = 3;
+        let matrix = [[1, 2, 3], [4, 5, 6], [7, 8, 9]];
+
+        let mut rotated = [[0u8; SIZE]; SIZE];
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                rotated[x][SIZE - 1 - y] = matrix[y][x];
+            }
+        }
+        let mut legacy = [[0u8; SIZE]; SIZE];
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                legacy[y][SIZE - 1 - x] = rotated[y][x];
+            }
+        }
+
+        let board = Board::from_matrix(SIZE, SIZE, Orientation::Transpose, |i, j| matrix[i][j]);
+        for (y, row) in legacy.iter().enumerate() {
+            for (x, &color) in row.iter().enumerate() {
+                assert_eq!(board.get(x as i32, y as i32), Some(color));
+            }
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trips_rows() {
+        let board = Board::from_matrix(2, 3, Orientation::Transpose, |i, j| MATRIX[i][j]);
+        let saved: String = board
+            .rows()
+            .map(|row| row.iter().map(|id| format!("{:2} ", id)).collect::<String>() + "\n")
+            .collect();
+        assert_eq!(Board::parse_snapshot(&saved).unwrap(), board);
+        assert!(Board::parse_snapshot(" 1  2\n 3\n").is_err());
+    }
+
+    #[test]
+    fn board_coordinates_match_server_coordinates_with_transpose() {
+        // Le serveur stocke board[x][y] : le pixel (x=1, y=2) est MATRIX[1][2]
+        let board = Board::from_matrix(2, 3, Orientation::Transpose, |i, j| MATRIX[i][j]);
+        assert_eq!((board.width(), board.height()), (2, 3));
+        assert_eq!(board.get(1, 2), Some(6));
+        assert_eq!(board.get(2, 1), None);
+    }
+
+    #[test]
+    fn empty_boards_have_no_rows() {
+        let board = Board::parse_snapshot("").unwrap();
+        assert_eq!((board.width(), board.height()), (0, 0));
+        assert_eq!(board.rows().count(), 0);
+        assert_eq!(Board::from_matrix(0, 0, Orientation::None, |_, _| 1).rows().count(), 0);
+    }
+
+    #[test]
+    fn crop_is_reduced_to_the_board() {
+        let board = Board::from_matrix(2, 3, Orientation::None, |i, j| MATRIX[i][j]);
+        let rows = |board: &Board| -> Vec<Vec<u8>> { board.rows().map(|row| row.to_vec()).collect() };
+
+        assert_eq!(rows(&board.crop(1, 0, 2, 2)), vec![vec![2, 3], vec![5, 6]]);
+        assert_eq!(rows(&board.crop(2, 1, 10, 10)), vec![vec![6]]);
+        assert_eq!(rows(&board.crop(1, 1, usize::MAX, usize::MAX)), vec![vec![5, 6]]);
+        for (x, y) in [(3, 0), (7, 0), (0, 2)] {
+            let cropped = board.crop(x, y, 2, 2);
+            assert_eq!((cropped.width(), cropped.height()), (0, 0));
+        }
+    }
+
+    #[test]
+    fn diff_lists_changes_on_the_common_area() {
+        let before = Board::from_matrix(2, 3, Orientation::None, |i, j| MATRIX[i][j]);
+        let after = Board::from_matrix_with_info(3, 2, Orientation::None, |i, j| {
+            let color = if (i, j) == (1, 0) { 9 } else { MATRIX.get(i).map_or(0, |row| row[j]) };
+            (color, PixelInfo::new("bob", "2024-01-01T00:00:00Z"))
+        });
+
+        // Zone commune de 2x2 : seule la case (0, 1) a changé, la 3e colonne et la 3e ligne sont ignorées
+        let changes = before.diff(&after);
+        assert_eq!(changes.len(), 1);
+        let change = &changes[0];
+        assert_eq!((change.x, change.y, change.before, change.after), (0, 1, 4, 9));
+        assert_eq!(change.info.username_or_unknown(), "bob");
+        assert!(before.diff(&before).is_empty());
+    }
+}
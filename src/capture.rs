@@ -0,0 +1,36 @@
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+
+use crate::args_parser::CaptureArgs;
+use crate::board::Board;
+use crate::pattern::{Pattern, PatternPixel, PixelColor};
+
+/// Pattern qui reproduit le rectangle de la board, sans les couleurs exclues
+pub fn board_to_pattern(board: &Board, x: usize, y: usize, width: usize, height: usize, exclude: &[u8]) -> Pattern {
+    let region = board.crop(x, y, width, height);
+    let pixels = region
+        .iter()
+        .filter(|(_, _, color)| !exclude.contains(color))
+        .map(|(x, y, color)| PatternPixel { x: x as i32, y: y as i32, color: PixelColor::Exact(color), weight: None })
+        .collect();
+    Pattern::new(region.width() as u32, region.height() as u32, pixels)
+}
+
+/// Sous-commande `capture` : écrit le rectangle demandé de la board en pattern
+pub fn run(args: &CaptureArgs, board: &Board) -> Result<()> {
+    if !board.contains(args.x as i32, args.y as i32) {
+        return Err(anyhow!("({}, {}) is outside of the {}x{} board", args.x, args.y, board.width(), board.height()));
+    }
+
+    let pattern = board_to_pattern(board, args.x, args.y, args.width, args.height, &args.exclude);
+    let (width, height) = pattern.size();
+    if (width as usize, height as usize) != (args.width, args.height) {
+        warn!("Capture reduced to {}x{} to stay on the {}x{} board", width, height, board.width(), board.height());
+    }
+
+    pattern.save(&args.output)?;
+    info!("Captured {}x{} at ({}, {}), {} pixels written to {}",
+        width, height, args.x, args.y, pattern.pixels().count(), args.output.display());
+    info!("Use it with --pattern \"{} {} {} <priority>\"", args.output.display(), args.x, args.y);
+    Ok(())
+}
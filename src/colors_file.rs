@@ -0,0 +1,40 @@
+// Format des fichiers `colors.txt` et `colors_<timestamp>.txt` : une couleur par ligne,
+// "Color 7: orange (RGB: 230,126,34)".
+//
+// Partagé avec le serveur de test (`src/bin/mock_server.rs`), qui l'inclut avec `#[path]`.
+
+use anyhow::{Result, anyhow};
+
+/// Une ligne de `colors.txt`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorLine {
+    pub id: u8,
+    pub name: String,
+    pub rgb: [u8; 3],
+}
+
+/// Lit toutes les lignes non vides, dans l'ordre du fichier
+pub fn parse(content: &str) -> Result<Vec<ColorLine>> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let invalid = || anyhow!("Invalid color line: {}", line);
+            let rest = line.trim().strip_prefix("Color ").ok_or_else(invalid)?;
+            let (id, rest) = rest.split_once(':').ok_or_else(invalid)?;
+            let (name, rgb) = rest.split_once("(RGB:").ok_or_else(invalid)?;
+            let rgb: Vec<u8> = rgb
+                .trim_end_matches(')')
+                .split(',')
+                .map(|c| c.trim().parse::<u8>())
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?;
+            let rgb: [u8; 3] = rgb.try_into().map_err(|_| invalid())?;
+            Ok(ColorLine {
+                id: id.trim().parse().map_err(|_| invalid())?,
+                name: name.trim().to_string(),
+                rgb,
+            })
+        })
+        .collect()
+}
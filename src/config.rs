@@ -1,9 +1,381 @@
-use std::time::Duration;
+use anyhow::{Result, Context, anyhow};
+use log::info;
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-// Constants
-pub const MAX_PIXELS_PER_BATCH: usize = 10;
-pub const BATCH_DELAY_MINUTES: u64 = 31;
-pub const MAX_RETRIES: u32 = 10;
-pub const RETRY_DELAY: Duration = Duration::from_secs(120); // 2 minutes
-pub const BOARD_SIZE: usize = 250;
+use crate::args_parser::{parse_patterns, Args, ArgSpecs};
+use crate::board::Orientation;
+use crate::manifest::load_manifest;
+use crate::planner::PixelOrder;
+use crate::place_client::{
+    DEFAULT_BASE_URL,
+    DEFAULT_BOARD_ENDPOINT,
+    DEFAULT_SET_ENDPOINT,
+};
 
+// Valeurs par défaut, utilisées quand ni le fichier de config ni la CLI ne les fixent
+pub const DEFAULT_MAX_PIXELS_PER_BATCH: usize = 10;
+pub const DEFAULT_BATCH_DELAY_MINUTES: u64 = 31;
+pub const DEFAULT_MAX_RETRIES: u32 = 10;
+pub const DEFAULT_RETRY_DELAY_SECS: u64 = 120; // 2 minutes
+pub const DEFAULT_SNAPSHOT_DIR: &str = "map";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub base_url: String,
+    pub board_endpoint: String,
+    pub set_endpoint: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            board_endpoint: DEFAULT_BOARD_ENDPOINT.to_string(),
+            set_endpoint: DEFAULT_SET_ENDPOINT.to_string(),
+        }
+    }
+}
+
+/// Pixel dont on connaît la couleur en coordonnées du serveur, vérifié au démarrage
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OrientationCheck {
+    pub x: i32,
+    pub y: i32,
+    pub color: u8,
+}
+
+/// Contenu de `place.toml`, complété par les options de la ligne de commande
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub max_pixels_per_batch: usize,
+    pub batch_delay_minutes: u64,
+    pub max_retries: u32,
+    pub retry_delay_secs: u64,
+    pub snapshot_dir: PathBuf,
+    /// Journal JSONL des griefs, `<snapshot_dir>/griefs.jsonl` par défaut
+    pub grief_log: Option<PathBuf>,
+    /// Même format que `--pattern` : "path x y priority"
+    pub patterns: Vec<String>,
+    /// Manifest TOML/JSON listant les patterns, en plus de `patterns`
+    pub manifest: Option<PathBuf>,
+    pub server: ServerConfig,
+    pub orientation: Orientation,
+    pub orientation_check: Option<OrientationCheck>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_pixels_per_batch: DEFAULT_MAX_PIXELS_PER_BATCH,
+            batch_delay_minutes: DEFAULT_BATCH_DELAY_MINUTES,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay_secs: DEFAULT_RETRY_DELAY_SECS,
+            snapshot_dir: PathBuf::from(DEFAULT_SNAPSHOT_DIR),
+            grief_log: None,
+            patterns: Vec::new(),
+            manifest: None,
+            server: ServerConfig::default(),
+            orientation: Orientation::default(),
+            orientation_check: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read config file {}", path.display()))?;
+        toml::from_str(&content)
+            .map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Charge le fichier de config s'il y en a un, puis applique les options de la CLI par dessus
+    pub fn from_args(args: &Args) -> Result<Self> {
+        let mut config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        if let Some(value) = args.max_pixels_per_batch {
+            config.max_pixels_per_batch = value;
+        }
+        if let Some(value) = args.batch_delay_minutes {
+            config.batch_delay_minutes = value;
+        }
+        if let Some(value) = args.max_retries {
+            config.max_retries = value;
+        }
+        if let Some(value) = args.retry_delay_secs {
+            config.retry_delay_secs = value;
+        }
+        if let Some(value) = &args.snapshot_dir {
+            config.snapshot_dir = value.clone();
+        }
+        if let Some(value) = &args.grief_log {
+            config.grief_log = Some(value.clone());
+        }
+        if !args.patterns.is_empty() {
+            config.patterns = args.patterns.clone();
+        }
+        if let Some(value) = &args.manifest {
+            config.manifest = Some(value.clone());
+        }
+        if let Some(value) = args.orientation {
+            config.orientation = value;
+        }
+        if let Some(value) = &args.base_url {
+            config.server.base_url = value.clone();
+        }
+        if let Some(value) = &args.board_endpoint {
+            config.server.board_endpoint = value.clone();
+        }
+        if let Some(value) = &args.set_endpoint {
+            config.server.set_endpoint = value.clone();
+        }
+
+        Ok(config)
+    }
+
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_delay_secs)
+    }
+
+    pub fn grief_log_path(&self) -> PathBuf {
+        self.grief_log
+            .clone()
+            .unwrap_or_else(|| self.snapshot_dir.join("griefs.jsonl"))
+    }
+
+    /// Problèmes des réglages généraux et du serveur, sans regarder les patterns
+    fn settings_errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.max_pixels_per_batch == 0 {
+            errors.push("max_pixels_per_batch must be at least 1".to_string());
+        }
+        if self.batch_delay_minutes == 0 {
+            errors.push("batch_delay_minutes must be at least 1".to_string());
+        }
+        if self.retry_delay_secs == 0 {
+            errors.push("retry_delay_secs must be at least 1".to_string());
+        }
+        if self.snapshot_dir.as_os_str().is_empty() {
+            errors.push("snapshot_dir must not be empty".to_string());
+        }
+        // Le dossier par défaut, `snapshot_dir`, est créé au démarrage ; un `grief_log` ailleurs doit déjà avoir le sien
+        if let Some(dir) = self.grief_log.as_deref().and_then(Path::parent) {
+            if !dir.as_os_str().is_empty() && !dir.is_dir() {
+                errors.push(format!("grief_log: directory not found: {}", dir.display()));
+            }
+        }
+
+        let base_url = &self.server.base_url;
+        if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            errors.push(format!("server.base_url must start with http:// or https://, got {:?}", base_url));
+        }
+        for (name, endpoint) in [
+            ("server.board_endpoint", &self.server.board_endpoint),
+            ("server.set_endpoint", &self.server.set_endpoint),
+        ] {
+            if !endpoint.starts_with('/') {
+                errors.push(format!("{} must start with '/', got {:?}", name, endpoint));
+            }
+        }
+        errors
+    }
+
+    /// Pour les sous-commandes qui contactent le serveur sans utiliser de pattern
+    pub fn validate_settings(&self) -> Result<()> {
+        let errors = self.settings_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid configuration:\n  - {}", errors.join("\n  - ")));
+        }
+        Ok(())
+    }
+
+    /// Vérifie toute la config d'un coup et renvoie la liste complète des problèmes trouvés
+    pub fn validate(&self) -> Result<Vec<ArgSpecs>> {
+        let mut errors = self.settings_errors();
+
+        let mut specs = Vec::new();
+        for pattern in &self.patterns {
+            match parse_patterns(pattern) {
+                Ok(spec) => specs.push(spec),
+                Err(e) => errors.push(e),
+            }
+        }
+        if let Some(manifest) = &self.manifest {
+            match load_manifest(manifest) {
+                Ok(manifest_specs) => specs.extend(manifest_specs),
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+
+        make_names_unique(&mut specs);
+
+        if specs.is_empty() && errors.is_empty() {
+            errors.push("no pattern given (use --pattern, --manifest or `patterns` in the config file)".to_string());
+        }
+        for spec in &specs {
+            if !Path::new(&spec.pattern_path).is_file() {
+                errors.push(format!("pattern {:?}: file not found: {}", spec.name, spec.pattern_path));
+            }
+            if spec.options.max_pixels_per_batch == Some(0) {
+                errors.push(format!("pattern {:?}: options.max_pixels_per_batch must be at least 1", spec.name));
+            }
+            if spec.options.seed.is_some() && spec.options.order != Some(PixelOrder::Random) {
+                errors.push(format!("pattern {:?}: options.seed is only used with order = \"random\"", spec.name));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow!("Invalid configuration:\n  - {}", errors.join("\n  - ")));
+        }
+
+        // sort est stable : à priorité égale, l'ordre de déclaration est conservé
+        specs.sort();
+        Ok(specs)
+    }
+}
+
+/// Le nom par défaut d'un pattern est le nom de son fichier : les doublons (même fichier à deux
+/// endroits, ou même nom dans deux dossiers) deviennent `core#2`, `core#3`... dans l'ordre de déclaration
+fn make_names_unique(specs: &mut [ArgSpecs]) {
+    let mut taken: HashSet<String> = HashSet::new();
+    for spec in specs.iter_mut() {
+        if taken.contains(&spec.name) {
+            let name = (2..)
+                .map(|n| format!("{}#{}", spec.name, n))
+                .find(|name| !taken.contains(name))
+                .expect("some suffix is free");
+            info!("Pattern {} ({}) renamed to {} to keep names unique", spec.name, spec.pattern_path, name);
+            spec.name = name;
+        }
+        taken.insert(spec.name.clone());
+    }
+}
+
+/// Fichier `<prefix>_<timestamp>.txt` le plus récent de `snapshot_dir`
+pub fn latest_snapshot_file(snapshot_dir: &Path, prefix: &str) -> Option<PathBuf> {
+    let prefix = format!("{}_", prefix);
+    fs::read_dir(snapshot_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(&prefix) && name.ends_with(".txt"))
+        })
+        // Les timestamps sont au format %Y-%m-%d_%H-%M-%S, l'ordre alphabétique suffit
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn args(extra: &[&str]) -> Args {
+        Args::parse_from([&["place_client", "--dry-run"], extra].concat())
+    }
+
+    #[test]
+    fn defaults_without_config_file() {
+        let config = Config::from_args(&args(&[])).unwrap();
+        assert_eq!(config.max_pixels_per_batch, DEFAULT_MAX_PIXELS_PER_BATCH);
+        assert_eq!(config.batch_delay_minutes, DEFAULT_BATCH_DELAY_MINUTES);
+        assert_eq!(config.retry_delay_secs, DEFAULT_RETRY_DELAY_SECS);
+        assert_eq!(config.snapshot_dir, PathBuf::from(DEFAULT_SNAPSHOT_DIR));
+        assert_eq!(config.grief_log_path(), PathBuf::from("map/griefs.jsonl"));
+        assert_eq!(config.server.base_url, DEFAULT_BASE_URL);
+        assert!(config.validate_settings().is_ok());
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let path = std::env::temp_dir().join(format!("place_config_{}.toml", std::process::id()));
+        fs::write(&path, r#"
+max_pixels_per_batch = 4
+batch_delay_minutes = 5
+patterns = ["pattern/core.json 1 2 1"]
+
+[server]
+base_url = "http://file.example"
+"#).unwrap();
+        let config = Config::from_args(&args(&[
+            "--config", path.to_str().unwrap(),
+            "--max-pixels-per-batch", "7",
+            "--pattern", "pattern/flag.json 3 4 2",
+        ])).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.max_pixels_per_batch, 7);
+        assert_eq!(config.batch_delay_minutes, 5);
+        assert_eq!(config.retry_delay_secs, DEFAULT_RETRY_DELAY_SECS);
+        assert_eq!(config.patterns, ["pattern/flag.json 3 4 2"]);
+        assert_eq!(config.server.base_url, "http://file.example");
+        assert_eq!(config.server.set_endpoint, DEFAULT_SET_ENDPOINT);
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let config = Config {
+            max_pixels_per_batch: 0,
+            batch_delay_minutes: 0,
+            retry_delay_secs: 0,
+            snapshot_dir: PathBuf::new(),
+            grief_log: Some(PathBuf::from("missing-dir/griefs.jsonl")),
+            patterns: vec!["pattern/core.json 1 2".to_string(), "missing.json 0 0 1".to_string()],
+            server: ServerConfig {
+                base_url: "ftp://example".to_string(),
+                board_endpoint: "api/get".to_string(),
+                set_endpoint: "/api/set".to_string(),
+            },
+            ..Config::default()
+        };
+        let message = config.validate().unwrap_err().to_string();
+        for expected in [
+            "max_pixels_per_batch must be at least 1",
+            "batch_delay_minutes must be at least 1",
+            "retry_delay_secs must be at least 1",
+            "snapshot_dir must not be empty",
+            "grief_log: directory not found: missing-dir",
+            "server.base_url must start with http:// or https://",
+            "server.board_endpoint must start with '/'",
+            "Invalid pattern arguments pattern/core.json 1 2",
+            "file not found: missing.json",
+        ] {
+            assert!(message.contains(expected), "{:?} not in {}", expected, message);
+        }
+        assert!(!message.contains("server.set_endpoint"));
+        assert!(config.validate_settings().is_err());
+
+        let message = Config::default().validate().unwrap_err().to_string();
+        assert!(message.contains("no pattern given"));
+    }
+
+    #[test]
+    fn duplicate_names_get_a_suffix() {
+        let config = Config {
+            patterns: vec![
+                "pattern/core.json 0 0 1".to_string(),
+                "pattern/core.json 50 0 1".to_string(),
+                "pattern/flag.json 0 50 2".to_string(),
+                "pattern/core.json 0 80 3".to_string(),
+            ],
+            ..Config::default()
+        };
+        let names: Vec<String> = config.validate().unwrap().into_iter().map(|spec| spec.name).collect();
+        assert_eq!(names, ["core", "core#2", "flag", "core#3"]);
+    }
+}
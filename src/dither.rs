@@ -0,0 +1,164 @@
+use clap::ValueEnum;
+use image::RgbaImage;
+
+use crate::palette::{ColorDistance, Palette};
+
+/// Méthode de tramage utilisée pour réduire l'image aux couleurs de la palette
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Dither {
+    /// Chaque pixel prend simplement la couleur la plus proche
+    #[default]
+    None,
+    FloydSteinberg,
+    Atkinson,
+    /// Tramage ordonné avec une matrice de Bayer (voir --bayer-size)
+    Ordered,
+}
+
+// Diffusion de l'erreur : (dx, dy, poids)
+const FLOYD_STEINBERG: [(i32, i32, f32); 4] = [
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+// Atkinson ne diffuse que 6/8 de l'erreur, ce qui garde plus de contraste
+const ATKINSON: [(i32, i32, f32); 6] = [
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+// Amplitude du bruit du tramage ordonné : avec 16 couleurs, il y a peu de niveaux par canal
+const ORDERED_SPREAD: f32 = 64.0;
+
+/// Matrice de Bayer `size x size` (puissance de 2), valeurs de 0 à size² - 1
+fn bayer_matrix(size: usize) -> Vec<Vec<u32>> {
+    let mut matrix = vec![vec![0u32]];
+    while matrix.len() < size {
+        let n = matrix.len();
+        let mut next = vec![vec![0u32; n * 2]; n * 2];
+        for y in 0..n {
+            for x in 0..n {
+                let v = matrix[y][x] * 4;
+                next[y][x] = v;
+                next[y][x + n] = v + 2;
+                next[y + n][x] = v + 3;
+                next[y + n][x + n] = v + 1;
+            }
+        }
+        matrix = next;
+    }
+    matrix
+}
+
+/// Id de palette de chaque pixel, ligne par ligne, `None` pour les pixels transparents
+pub fn quantize(
+    img: &RgbaImage,
+    palette: &Palette,
+    dither: Dither,
+    distance: ColorDistance,
+    alpha_threshold: u8,
+    bayer_size: usize,
+) -> Vec<Option<u8>> {
+    let width = img.width() as usize;
+    let height = img.height() as usize;
+    let mut buffer: Vec<[f32; 3]> = img
+        .pixels()
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+    let opaque: Vec<bool> = img.pixels().map(|p| p[3] >= alpha_threshold).collect();
+
+    let kernel: &[(i32, i32, f32)] = match dither {
+        Dither::FloydSteinberg => &FLOYD_STEINBERG,
+        Dither::Atkinson => &ATKINSON,
+        Dither::None | Dither::Ordered => &[],
+    };
+    let bayer = bayer_matrix(bayer_size);
+    let bayer_len = bayer.len();
+    let bayer_cells = (bayer_len * bayer_len) as f32;
+
+    let mut result = vec![None; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            if !opaque[i] {
+                continue;
+            }
+
+            let mut rgb = buffer[i].map(|c| c.clamp(0.0, 255.0));
+            if dither == Dither::Ordered {
+                let threshold = (bayer[y % bayer_len][x % bayer_len] as f32 + 0.5) / bayer_cells - 0.5;
+                rgb = rgb.map(|c| (c + threshold * ORDERED_SPREAD).clamp(0.0, 255.0));
+            }
+
+            let color = palette.nearest(rgb, distance);
+            result[i] = Some(color.id);
+
+            let error = [
+                rgb[0] - color.red as f32,
+                rgb[1] - color.green as f32,
+                rgb[2] - color.blue as f32,
+            ];
+            for &(dx, dy, weight) in kernel {
+                let nx = x as i32 + dx;
+                let ny = y as i32 + dy;
+                if nx < 0 || nx >= width as i32 || ny >= height as i32 {
+                    continue;
+                }
+                let target = &mut buffer[ny as usize * width + nx as usize];
+                for k in 0..3 {
+                    target[k] += error[k] * weight;
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    // Gris moyen 128 sur une palette noir (1) / blanc (2) : sans tramage tout devient blanc
+    fn dithered(dither: Dither, bayer_size: usize) -> Vec<Vec<u8>> {
+        let palette = Palette::parse("Color 1: black (RGB: 0,0,0)\nColor 2: white (RGB: 255,255,255)").unwrap();
+        let img = RgbaImage::from_pixel(4, 4, Rgba([128, 128, 128, 255]));
+        quantize(&img, &palette, dither, ColorDistance::Rgb, 128, bayer_size)
+            .chunks(4)
+            .map(|row| row.iter().map(|id| id.unwrap()).collect())
+            .collect()
+    }
+
+    const CHECKER: [[u8; 4]; 4] = [[2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2]];
+
+    #[test]
+    fn no_dither_keeps_the_nearest_color() {
+        assert_eq!(dithered(Dither::None, 4), vec![vec![2; 4]; 4]);
+    }
+
+    #[test]
+    fn bayer_matrices() {
+        assert_eq!(bayer_matrix(2), [[0, 2], [3, 1]]);
+        assert_eq!(bayer_matrix(4), [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]]);
+        // Seuil 98 + 4 * valeur : blanc là où la matrice vaut au moins 8
+        let inverse_checker = [[1, 2, 1, 2], [2, 1, 2, 1], [1, 2, 1, 2], [2, 1, 2, 1]];
+        assert_eq!(dithered(Dither::Ordered, 2), inverse_checker);
+        assert_eq!(dithered(Dither::Ordered, 4), inverse_checker);
+    }
+
+    #[test]
+    fn floyd_steinberg_gives_a_checkerboard() {
+        assert_eq!(dithered(Dither::FloydSteinberg, 4), CHECKER);
+    }
+
+    #[test]
+    fn atkinson_diffuses_its_own_pattern() {
+        assert_eq!(dithered(Dither::Atkinson, 4), [[2, 1, 1, 2], [1, 2, 2, 1], [1, 2, 2, 1], [2, 1, 1, 2]]);
+    }
+}
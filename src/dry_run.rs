@@ -0,0 +1,75 @@
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+use chrono::Utc;
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::args_parser::ArgSpecs;
+use crate::board::Board;
+use crate::config::Config;
+use crate::pattern::Pattern;
+use crate::place_client::Color;
+use crate::planner::PlacementPlan;
+
+/// Un pixel que le client placerait, avec le numéro du batch dans lequel il partirait
+#[derive(Serialize, Debug)]
+pub struct PlannedPixel {
+    pub batch: usize,
+    pub x: i32,
+    pub y: i32,
+    pub color: u8,
+    pub pattern: String,
+}
+
+/// Simule les batches de la boucle principale sur la board et les patterns déjà chargés au démarrage,
+/// sans jamais appeler `place_pixel`
+pub fn run(
+    config: &Config,
+    colors: &HashMap<u8, Color>,
+    board: &Board,
+    loaded: &[(&ArgSpecs, &Pattern)],
+    output: Option<&Path>,
+) -> Result<()> {
+    for (spec, pattern) in loaded {
+        if pattern.clipped() > 0 {
+            let (width, height) = pattern.size();
+            warn!("Pattern {}: {} pixels outside its {}x{} box were clipped",
+                spec.name, pattern.clipped(), width, height);
+        }
+    }
+
+    // Même plan que la boucle principale, découpé en batches comme si la board ne changeait plus
+    let plan = PlacementPlan::build(loaded, board, colors, Utc::now());
+    plan.log_summary(config.max_pixels_per_batch);
+    let batches = plan.batches(config.max_pixels_per_batch);
+
+    let mut planned = Vec::new();
+    for (i, batch) in batches.iter().enumerate() {
+        for entry in batch {
+            planned.push(PlannedPixel {
+                batch: i + 1,
+                x: entry.x,
+                y: entry.y,
+                color: entry.color,
+                pattern: plan.pattern_name(entry).to_string(),
+            });
+        }
+    }
+
+    for pixel in &planned {
+        println!("batch {:>3}  ({:>3}, {:>3})  color {:>2}  {}",
+            pixel.batch, pixel.x, pixel.y, pixel.color, pixel.pattern);
+    }
+
+    // Le premier batch part tout de suite, chacun des suivants attend le délai entre batches
+    let minutes = batches.len().saturating_sub(1) as u64 * config.batch_delay_minutes;
+    println!("{} pixels to place in {} batches, estimated time to finish: {}h {}m",
+        planned.len(), batches.len(), minutes / 60, minutes % 60);
+
+    if let Some(output) = output {
+        fs::write(output, serde_json::to_string_pretty(&planned)?)?;
+        info!("Placement plan written to {}", output.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use log::{info, warn, debug};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::args_parser::ArgSpecs;
+use crate::board::Board;
+use crate::pattern::{Pattern, PixelColor};
+use crate::planner::build_target_map;
+
+/// Un pixel d'un pattern qui était correct au fetch précédent et ne l'est plus
+#[derive(Serialize, Debug, Clone)]
+pub struct GriefEvent {
+    pub detected_at: String,
+    pub pattern: String,
+    pub x: i32,
+    pub y: i32,
+    pub expected_color: PixelColor,
+    pub old_color: u8,
+    pub new_color: u8,
+    pub username: Option<String>,
+    pub set_time: Option<String>,
+}
+
+/// Compare les boards successives aux patterns actifs et écrit chaque grief dans un fichier JSONL
+pub struct GriefTracker {
+    log_path: PathBuf,
+    cycle_events: Vec<GriefEvent>,
+}
+
+impl GriefTracker {
+    pub fn new(log_path: PathBuf) -> Self {
+        GriefTracker {
+            log_path,
+            cycle_events: Vec::new(),
+        }
+    }
+
+    /// Relève les pixels passés de correct à incorrect entre `previous` et `current`.
+    /// Les griefs restent dans le résumé du cycle même si le journal ne peut pas être écrit.
+    pub fn observe(&mut self, previous: &Board, current: &Board, patterns: &[(&ArgSpecs, &Pattern)]) -> Result<usize> {
+        let events = detect(previous, current, patterns, Utc::now());
+        if events.is_empty() {
+            return Ok(0);
+        }
+
+        for event in &events {
+            warn!("Grief on {} at ({}, {}): {} -> {} by {}",
+                event.pattern, event.x, event.y, event.old_color, event.new_color,
+                event.username.as_deref().unwrap_or("<unknown>"));
+        }
+        let count = events.len();
+        self.cycle_events.extend(events);
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)
+            .with_context(|| format!("Couldn't open grief log {}", self.log_path.display()))?;
+        for event in &self.cycle_events[self.cycle_events.len() - count..] {
+            writeln!(file, "{}", serde_json::to_string(event)?)
+                .with_context(|| format!("Couldn't write grief log {}", self.log_path.display()))?;
+        }
+        Ok(count)
+    }
+
+    /// Affiche le résumé des griefs du cycle puis le remet à zéro
+    pub fn end_cycle(&mut self) {
+        if self.cycle_events.is_empty() {
+            debug!("Grief summary: no pattern pixel lost this cycle");
+            return;
+        }
+
+        let mut by_pattern: HashMap<&str, usize> = HashMap::new();
+        let mut by_user: HashMap<&str, usize> = HashMap::new();
+        for event in &self.cycle_events {
+            *by_pattern.entry(&event.pattern).or_default() += 1;
+            *by_user.entry(event.username.as_deref().unwrap_or("<unknown>")).or_default() += 1;
+        }
+
+        info!("Grief summary: {} pattern pixels lost this cycle (logged to {})",
+            self.cycle_events.len(), self.log_path.display());
+        info!("  by pattern: {}", format_counts(by_pattern));
+        info!("  by user: {}", format_counts(by_user));
+
+        self.cycle_events.clear();
+    }
+}
+
+/// Griefs entre deux boards, une case à la fois : seule compte la couleur du pattern qui gagne la case,
+/// pour que nos propres réparations d'une case disputée ne soient pas prises pour des griefs
+fn detect(previous: &Board, current: &Board, patterns: &[(&ArgSpecs, &Pattern)], detected_at: DateTime<Utc>) -> Vec<GriefEvent> {
+    let mut targets: Vec<_> = build_target_map(patterns, current).into_iter().collect();
+    targets.sort_by_key(|&((x, y), _)| (y, x));
+
+    let mut events = Vec::new();
+    for ((x, y), target) in targets {
+        let expected = &target.color;
+        let (Some(old_color), Some(new_color)) = (previous.get(x, y), current.get(x, y)) else {
+            continue;
+        };
+
+        // Un pixel "any" ne peut pas être grief, une liste seulement en sortant de ses couleurs
+        if expected.accepts(old_color) && !expected.accepts(new_color) {
+            let pixel_info = current.info(x, y).cloned().unwrap_or_default();
+            events.push(GriefEvent {
+                detected_at: detected_at.to_rfc3339(),
+                pattern: patterns[target.pattern].0.name.clone(),
+                x,
+                y,
+                expected_color: expected.clone(),
+                old_color,
+                new_color,
+                username: pixel_info.username,
+                set_time: pixel_info.set_time.map(|time| time.to_rfc3339()),
+            });
+        }
+    }
+    events
+}
+
+fn format_counts(counts: HashMap<&str, usize>) -> String {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args_parser::PatternOptions;
+    use crate::board::{Orientation, PixelInfo};
+    use crate::pattern::PatternPixel;
+    use crate::test_utils::{exact_row, spec};
+
+    fn board(colors: &[u8]) -> Board {
+        Board::from_matrix_with_info(1, colors.len(), Orientation::None, |_, j| {
+            (colors[j], PixelInfo::new("mallory", "2024-01-01T00:00:00Z"))
+        })
+    }
+
+    #[test]
+    fn only_correct_to_wrong_cells_of_the_winner_are_griefs() {
+        use PixelColor::*;
+        let colors = [Exact(2), Exact(2), Any, OneOf(vec![3, 4]), Exact(2), OneOf(vec![3, 4])];
+        let mut pixels: Vec<PatternPixel> = colors
+            .into_iter()
+            .enumerate()
+            .map(|(x, color)| PatternPixel { x: x as i32, y: 0, color, weight: None })
+            .collect();
+        // Case listée deux fois dans le fichier : un seul grief
+        pixels.push(pixels[4].clone());
+        let main = Pattern::new(6, 1, pixels);
+        let other = exact_row(&[5]);
+        let specs = [spec("main", 0, 0, 1, PatternOptions::default()), spec("other", 0, 0, 2, PatternOptions::default())];
+        let patterns = [(&specs[0], &main), (&specs[1], &other)];
+
+        // 0 : case disputée, 5 -> 2 est notre réparation pour main, pas un grief contre other
+        // 1 : faux -> faux ; 2 : "any" ; 3 : 3 -> 4 reste dans la liste
+        // 4 : correct -> faux ; 5 : sort de la liste
+        let previous = board(&[5, 1, 1, 3, 2, 4]);
+        let current = board(&[2, 6, 7, 4, 9, 1]);
+        let events = detect(&previous, &current, &patterns, Utc::now());
+
+        let found: Vec<(&str, i32, u8, u8)> = events.iter().map(|e| (e.pattern.as_str(), e.x, e.old_color, e.new_color)).collect();
+        assert_eq!(found, [("main", 4, 2, 9), ("main", 5, 4, 1)]);
+        assert_eq!(events[0].username.as_deref(), Some("mallory"));
+
+        // Sur la case disputée, seule la couleur du gagnant compte : 2 -> 5 est un grief contre main
+        let events = detect(&board(&[2]), &board(&[5]), &patterns, Utc::now());
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].pattern.as_str(), &events[0].expected_color), ("main", &Exact(2)));
+    }
+
+    #[test]
+    fn unwritable_log_keeps_the_cycle_summary() {
+        let specs = [spec("main", 0, 0, 1, PatternOptions::default())];
+        let pattern = exact_row(&[2]);
+        let log_path = std::env::temp_dir().join(format!("missing-{}", std::process::id())).join("griefs.jsonl");
+        let mut tracker = GriefTracker::new(log_path);
+
+        let result = tracker.observe(&board(&[2]), &board(&[5]), &[(&specs[0], &pattern)]);
+        assert!(result.unwrap_err().to_string().starts_with("Couldn't open grief log"));
+        assert_eq!(tracker.cycle_events.len(), 1);
+    }
+}
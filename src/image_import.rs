@@ -0,0 +1,138 @@
+use anyhow::{Result, Context, anyhow};
+use clap::ValueEnum;
+use image::{imageops::{self, FilterType}, RgbaImage};
+use log::info;
+
+use crate::args_parser::ImportImageArgs;
+use crate::dither::{quantize, Dither};
+use crate::palette::{ColorDistance, Palette};
+use crate::pattern::{Pattern, PatternPixel, PixelColor};
+use crate::transform::{check_edge_weight_output, edge_weight};
+
+/// Filtre utilisé pour redimensionner l'image avant la conversion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    Lanczos3,
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Nearest => FilterType::Nearest,
+            ResizeFilter::Triangle => FilterType::Triangle,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Taille finale : celle demandée, en gardant le ratio si une seule dimension est donnée
+fn target_size(width: u32, height: u32, target_width: Option<u32>, target_height: Option<u32>) -> (u32, u32) {
+    let scaled = |value: u32, from: u32, to: u32| ((value as f64 * to as f64 / from as f64).round() as u32).max(1);
+    match (target_width, target_height) {
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, scaled(height, width, w)),
+        (None, Some(h)) => (scaled(width, height, h), h),
+        (None, None) => (width, height),
+    }
+}
+
+/// Associe chaque pixel à une couleur de la palette, les pixels transparents sont ignorés
+pub fn image_to_pattern(img: &RgbaImage, palette: &Palette, args: &ImportImageArgs) -> Pattern {
+    let width = img.width() as usize;
+    let pixels = quantize(img, palette, args.dither, args.distance, args.alpha_threshold, args.bayer_size)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, color)| {
+            color.map(|color| PatternPixel {
+                x: (i % width) as i32,
+                y: (i / width) as i32,
+                color: PixelColor::Exact(color),
+                weight: None,
+            })
+        })
+        .collect();
+
+    Pattern::new(img.width(), img.height(), pixels)
+}
+
+pub fn run(args: &ImportImageArgs) -> Result<()> {
+    check_edge_weight_output(args.edge_weight, &args.output)?;
+    let palette = Palette::load_or_bundled(args.palette.as_deref())?;
+    let img = image::open(&args.input)
+        .with_context(|| format!("Couldn't open image {}", args.input.display()))?
+        .to_rgba8();
+
+    let (width, height) = target_size(img.width(), img.height(), args.width, args.height);
+    if width == 0 || height == 0 {
+        return Err(anyhow!("Invalid target size {}x{}", width, height));
+    }
+    let img = if (width, height) != img.dimensions() {
+        info!("Resizing {}x{} image to {}x{}", img.width(), img.height(), width, height);
+        imageops::resize(&img, width, height, args.filter.into())
+    } else {
+        img
+    };
+
+    if args.dither != Dither::None || args.distance != ColorDistance::Rgb {
+        info!("Quantizing with {:?} dithering and {:?} color distance", args.dither, args.distance);
+    }
+
+    let mut pattern = image_to_pattern(&img, &palette, args);
+    if let Some(weight) = args.edge_weight {
+        pattern = edge_weight(&pattern, weight);
+    }
+    pattern.save(&args.output)?;
+    info!("Pattern {}x{} with {} pixels written to {}",
+        width, height, pattern.pixels().count(), args.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args_parser::{Args, Command};
+    use clap::Parser;
+    use image::Rgba;
+
+    fn import_args(extra: &[&str]) -> ImportImageArgs {
+        let args = Args::parse_from([&["place_client", "import-image", "in.png", "-o", "out.json"], extra].concat());
+        match args.command {
+            Some(Command::ImportImage(args)) => args,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn target_size_keeps_the_ratio() {
+        assert_eq!(target_size(200, 100, None, None), (200, 100));
+        assert_eq!(target_size(200, 100, Some(50), None), (50, 25));
+        assert_eq!(target_size(200, 100, None, Some(10)), (20, 10));
+        assert_eq!(target_size(200, 100, Some(7), Some(9)), (7, 9));
+        // Jamais de dimension nulle, même pour une image très allongée
+        assert_eq!(target_size(1000, 1, Some(10), None), (10, 1));
+    }
+
+    #[test]
+    fn quantizes_opaque_pixels_to_the_nearest_color() {
+        let palette = Palette::bundled();
+        // red exact, blanc cassé, rouge presque transparent, noir semi-transparent
+        let img = RgbaImage::from_fn(4, 1, |x, _| match x {
+            0 => Rgba([231, 76, 60, 255]),
+            1 => Rgba([250, 250, 250, 255]),
+            2 => Rgba([231, 76, 60, 40]),
+            _ => Rgba([44, 62, 80, 128]),
+        });
+
+        let colors = |args: &ImportImageArgs| -> Vec<(i32, u8)> {
+            image_to_pattern(&img, &palette, args)
+                .pixels()
+                .map(|p| (p.x, p.color.preferred().unwrap()))
+                .collect()
+        };
+        assert_eq!(colors(&import_args(&[])), [(0, 6), (1, 1), (3, 4)]);
+        assert_eq!(colors(&import_args(&["--alpha-threshold", "200"])), [(0, 6), (1, 1)]);
+        assert_eq!(colors(&import_args(&["--alpha-threshold", "0"])), [(0, 6), (1, 1), (2, 6), (3, 4)]);
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use std::collections::HashMap;
+
+use crate::args_parser::{ArgSpecs, LocateArgs};
+use crate::board::Board;
+use crate::pattern::Pattern;
+
+/// Part de pixels faux acceptée pour `origin = "auto"`
+pub const DEFAULT_TOLERANCE: f64 = 0.2;
+
+/// Une origine possible du pattern sur la board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub x: i32,
+    pub y: i32,
+    /// Pixels du pattern qui ont déjà la bonne couleur à cette origine
+    pub matched: usize,
+    /// Pixels comparés (les pixels `"any"` ne comptent pas)
+    pub total: usize,
+}
+
+impl Candidate {
+    pub fn score(&self) -> f64 {
+        self.matched as f64 / self.total as f64
+    }
+}
+
+/// Cherche les origines où le pattern tient entièrement sur la board avec au plus `tolerance`
+/// de pixels faux, et renvoie les `top` meilleures, de la plus ressemblante à la moins ressemblante.
+pub fn locate(pattern: &Pattern, board: &Board, tolerance: f64, top: usize) -> Vec<Candidate> {
+    let (width, height) = pattern.size();
+    if top == 0 || width as usize > board.width() || height as usize > board.height() {
+        return Vec::new();
+    }
+
+    // Les couleurs rares de la board en premier : une mauvaise origine est écartée plus vite
+    let mut frequency: HashMap<u8, usize> = HashMap::new();
+    for (_, _, color) in board.iter() {
+        *frequency.entry(color).or_default() += 1;
+    }
+    let mut pixels: Vec<_> = pattern.pixels().filter(|p| p.color.preferred().is_some()).collect();
+    pixels.sort_by_key(|p| p.color.ids().iter().map(|id| frequency.get(id).copied().unwrap_or(0)).sum::<usize>());
+
+    let total = pixels.len();
+    if total == 0 {
+        return Vec::new();
+    }
+    let max_mismatches = (tolerance.clamp(0.0, 1.0) * total as f64).floor() as usize;
+
+    // (pixels faux, candidat), trié du meilleur au moins bon
+    let mut best: Vec<(usize, Candidate)> = Vec::new();
+    'search: for y in 0..=(board.height() - height as usize) as i32 {
+        for x in 0..=(board.width() - width as usize) as i32 {
+            // Une origine qui ne peut plus entrer dans le classement est abandonnée
+            let limit = if best.len() < top {
+                max_mismatches
+            } else {
+                match best[top - 1].0.checked_sub(1) {
+                    Some(limit) => limit.min(max_mismatches),
+                    // Le classement n'a que des correspondances parfaites
+                    None => break 'search,
+                }
+            };
+            let mut mismatches = 0;
+            for p in &pixels {
+                let color = board.get(x + p.x, y + p.y).expect("pattern box fits on the board");
+                if !p.color.accepts(color) {
+                    mismatches += 1;
+                    if mismatches > limit {
+                        break;
+                    }
+                }
+            }
+            if mismatches > limit {
+                continue;
+            }
+
+            let candidate = Candidate { x, y, matched: total - mismatches, total };
+            let position = best.partition_point(|(m, _)| *m <= mismatches);
+            best.insert(position, (mismatches, candidate));
+            best.truncate(top);
+        }
+    }
+    best.into_iter().map(|(_, candidate)| candidate).collect()
+}
+
+/// Remplace l'origine des patterns `origin = "auto"` par la meilleure position trouvée sur la board
+pub fn resolve_auto_origins(specs: &mut [ArgSpecs], board: &Board) -> Result<()> {
+    for spec in specs.iter_mut().filter(|spec| spec.auto_origin) {
+        let pattern = Pattern::load(&spec.pattern_path)?;
+        let candidates = locate(&pattern, board, DEFAULT_TOLERANCE, 2);
+        let Some(best) = candidates.first() else {
+            return Err(anyhow!("Couldn't locate pattern {:?} on the board: no position with at most {}% wrong pixels",
+                spec.name, DEFAULT_TOLERANCE * 100.0));
+        };
+        if candidates.get(1).is_some_and(|second| second.matched == best.matched) {
+            warn!("Pattern {} matches as well at ({}, {}) as at ({}, {}), using the first one",
+                spec.name, candidates[1].x, candidates[1].y, best.x, best.y);
+        }
+        info!("Pattern {} located at ({}, {}), {}/{} pixels already match",
+            spec.name, best.x, best.y, best.matched, best.total);
+        spec.x = best.x;
+        spec.y = best.y;
+    }
+    Ok(())
+}
+
+/// Sous-commande `locate` : affiche les meilleures origines du pattern sur la board
+pub fn run(args: &LocateArgs, board: &Board) -> Result<()> {
+    let pattern = Pattern::load(&args.pattern.to_string_lossy())?;
+    let candidates = locate(&pattern, board, args.tolerance, args.top);
+    if candidates.is_empty() {
+        println!("No position on the {}x{} board with at most {:.0}% wrong pixels",
+            board.width(), board.height(), args.tolerance * 100.0);
+        return Ok(());
+    }
+    for (i, candidate) in candidates.iter().enumerate() {
+        println!("{:>2}. origin ({}, {}): {}/{} pixels match ({:.1}%)",
+            i + 1, candidate.x, candidate.y, candidate.matched, candidate.total, candidate.score() * 100.0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Orientation;
+    use crate::pattern::{PatternPixel, PixelColor};
+
+    #[test]
+    fn finds_the_damaged_copy() {
+        // Un L de couleur 2 posé en (5, 3) sur une board de couleur 1, avec un pixel abîmé
+        let placed = [(5, 3), (5, 4), (5, 5), (6, 5), (7, 5)];
+        let board = Board::from_matrix(10, 12, Orientation::None, |i, j| {
+            let damaged = (j, i) == (7, 5);
+            if placed.contains(&(j as i32, i as i32)) && !damaged { 2 } else { 1 }
+        });
+        let pattern = Pattern::new(3, 3, [(0, 0), (0, 1), (0, 2), (1, 2), (2, 2)]
+            .into_iter()
+            .map(|(x, y)| PatternPixel { x, y, color: PixelColor::Exact(2), weight: None })
+            .collect());
+
+        let candidates = locate(&pattern, &board, 0.2, 3);
+        assert_eq!(candidates[0], Candidate { x: 5, y: 3, matched: 4, total: 5 });
+        assert!(candidates.iter().skip(1).all(|c| c.matched <= 4));
+        assert!(locate(&pattern, &board, 0.0, 3).is_empty());
+    }
+}
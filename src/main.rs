@@ -1,83 +1,115 @@
 mod place_client;
 mod config;
 mod args_parser;
+mod ascii;
+mod board;
+mod capture;
+mod colors_file;
+mod dither;
+mod dry_run;
+mod grief;
+mod image_import;
+mod locate;
+mod manifest;
+mod overlap;
+mod palette;
+mod pattern;
+mod planner;
+mod render;
+#[cfg(test)]
+mod test_utils;
+mod transform;
+mod validate;
 
-use tokio;
 use anyhow::Result;
-use log::{info, LevelFilter};
+use log::{info, debug, error, warn, LevelFilter};
 use env_logger::Builder;
-use image::{ImageBuffer, Rgb};
 use chrono::{Local, Utc};
 use tokio::time::sleep;
 use clap::Parser;
 
 use std::{
     fs,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
     process::exit,
     time::Duration,
 };
 
 use args_parser::{
-    parse_patterns,
     Args,
-    ArgSpecs
+    ArgSpecs,
+    Command,
 };
 
-use config::{
-    MAX_PIXELS_PER_BATCH,
-    BATCH_DELAY_MINUTES,
-    MAX_RETRIES,
-    RETRY_DELAY,
-    BOARD_SIZE,
-};
+use board::Board;
+use config::Config;
+use grief::GriefTracker;
+use pattern::{Pattern, PatternCache};
+use planner::PlacementPlan;
 
 use place_client::{
     Color,
     PlaceClient,
-    Pattern,
     Auth,
 };
 
-fn save_board_state(colors: &HashMap<u8, Color>, board: &Vec<Vec<u8>>, timestamp: &str) -> Result<()> {
+fn save_board_state(dir: &Path, colors: &HashMap<u8, Color>, board: &Board, timestamp: &str) -> Result<()> {
     // Créer un fichier avec la correspondance des couleurs
     let mut color_info = String::new();
     for (id, color) in colors {
         color_info.push_str(&format!("Color {}: {} (RGB: {},{},{})\n", 
             id, color.name, color.red, color.green, color.blue));
     }
-    fs::write(format!("map/colors_{}.txt", timestamp), color_info)?;
+    fs::write(dir.join(format!("colors_{}.txt", timestamp)), color_info)?;
     
     // Sauvegarder la matrice
     let mut board_output = String::new();
-    for row in board.iter() {
+    for row in board.rows() {
         for color_id in row {
             board_output.push_str(&format!("{:2} ", color_id));
         }
         board_output.push('\n');
     }
-    fs::write(format!("map/board_{}.txt", timestamp), board_output)?;
+    fs::write(dir.join(format!("board_{}.txt", timestamp)), board_output)?;
     
     // Créer l'image PNG
-    let mut img = ImageBuffer::new(BOARD_SIZE as u32, BOARD_SIZE as u32);
-    for (y, row) in board.iter().enumerate() {
-        for (x, &color_id) in row.iter().enumerate() {
-            if let Some(color) = colors.get(&color_id) {
-                img.put_pixel(
-                    x as u32,
-                    y as u32,
-                    Rgb([color.red, color.green, color.blue])
-                ); 
-            }
-        }
-    }
-    img.save(format!("map/board_{}.png", timestamp))?;
-    info!("Board data saved to map folder with timestamp {}", timestamp);
+    let img = board.render(colors);
+    img.save(dir.join(format!("board_{}.png", timestamp)))?;
+    info!("Board data saved to {} with timestamp {}", dir.display(), timestamp);
     Ok(())
 }
 
 
 
+fn build_client(config: &Config) -> PlaceClient {
+    PlaceClient::builder()
+        .base_url(&config.server.base_url)
+        .board_endpoint(&config.server.board_endpoint)
+        .set_endpoint(&config.server.set_endpoint)
+        .max_retries(config.max_retries)
+        .retry_delay(config.retry_delay())
+        .batch_delay(Duration::from_secs(config.batch_delay_minutes * 60))
+        .orientation(config.orientation)
+        .build()
+}
+
+/// Dossier des snapshots d'une sous-commande : son option, sinon celui de la config (`map` par défaut)
+fn snapshot_dir(flag: Option<&Path>, config: &Config) -> PathBuf {
+    flag.map_or_else(|| config.snapshot_dir.clone(), Path::to_path_buf)
+}
+
+/// Board d'une sous-commande : le snapshot donné, sinon celle du serveur
+async fn load_board(snapshot: Option<&Path>, config: &Config) -> Result<Board> {
+    match snapshot {
+        Some(snapshot) => Board::load_snapshot(snapshot),
+        None => {
+            config.validate_settings()?;
+            Ok(build_client(config).get_board().await?.1)
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     Builder::new()
@@ -85,78 +117,188 @@ async fn main() -> Result<()> {
         .format_timestamp_millis()
         .init();
 
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::ImportImage(import_args)) => return image_import::run(import_args),
+        Some(Command::RenderPattern(render_args)) => {
+            let config = Config::from_args(&args)?;
+            return render::run(render_args, &snapshot_dir(render_args.snapshot_dir.as_deref(), &config));
+        }
+        Some(Command::Transform(transform_args)) => return transform::run(transform_args),
+        Some(Command::Capture(capture_args)) => {
+            let board = load_board(capture_args.snapshot.as_deref(), &Config::from_args(&args)?).await?;
+            return capture::run(capture_args, &board);
+        }
+        Some(Command::Locate(locate_args)) => {
+            let board = load_board(locate_args.snapshot.as_deref(), &Config::from_args(&args)?).await?;
+            return locate::run(locate_args, &board);
+        }
+        Some(Command::Overlaps(overlaps_args)) => {
+            let config = Config::from_args(&args)?;
+            let mut specs = config.validate()?;
+            let board = load_board(overlaps_args.snapshot.as_deref(), &config).await?;
+            locate::resolve_auto_origins(&mut specs, &board)?;
+            return overlap::run(overlaps_args, &specs, &board, &snapshot_dir(overlaps_args.snapshot_dir.as_deref(), &config));
+        }
+        Some(Command::Validate(validate_args)) => {
+            let config = Config::from_args(&args)?;
+            let specs = if validate_args.files.is_empty() {
+                config.validate()?
+            } else {
+                Vec::new()
+            };
+            return validate::run(validate_args, &specs, &snapshot_dir(validate_args.snapshot_dir.as_deref(), &config));
+        }
+        None => {}
+    }
+
     info!("Starting Place client with multiple patterns support");
 
-    let args = Args::parse();
-    // Get pattern path, x, y, and priority into a vector
-    let mut patterns: Vec<ArgSpecs> = args.patterns
-        .iter()
-        .filter_map(|pattern| {
-            match parse_patterns(pattern) {
-                Ok(pattern) => Some(pattern),
-                Err(e) => {
-                    eprintln!("Error parsing pattern: {} {}", e, pattern);
-                    exit(1);
-                }
-            }
-        })
-        .collect();
+    let config = match Config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+    // Get pattern path, x, y, and priority into a vector sorted by priority
+    let mut patterns: Vec<ArgSpecs> = match config.validate() {
+        Ok(patterns) => patterns,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    fs::create_dir_all(&config.snapshot_dir)?;
+
+    let client = build_client(&config);
 
-    patterns.sort();
+    // Vérifie l'orientation, puis les patterns contre la palette et la taille actuelles de la board,
+    // avant de placer quoi que ce soit
+    let (colors, board) = client.get_board().await?;
+    if let Some(check) = &config.orientation_check {
+        client.check_orientation(&board, check.x, check.y, check.color)?;
+    }
+    if let Err(e) = locate::resolve_auto_origins(&mut patterns, &board) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+    let color_ids: HashSet<u8> = colors.keys().copied().collect();
+    if let Err(e) = validate::check_patterns(&patterns, &color_ids, Some((board.width(), board.height()))) {
+        eprintln!("{}", e);
+        exit(1);
+    }
+    // Les patterns sont valides : on signale les cases disputées avant de commencer
+    let startup_patterns = pattern::load_patterns(&patterns)?;
+    let startup_patterns: Vec<(&ArgSpecs, &Pattern)> = patterns.iter().zip(&startup_patterns).collect();
+    overlap::analyze(&startup_patterns, &board).log();
 
-    fs::create_dir_all("map")?;
+    if args.dry_run {
+        return dry_run::run(&config, &colors, &board, &startup_patterns, args.plan_output.as_deref());
+    }
 
-    let client = PlaceClient::new()?;
+    // clap garantit la présence des tokens hors --dry-run
     let mut auth = Auth {
-        refresh_token: args.refresh_token,
-        token: args.token,
+        refresh_token: args.refresh_token.unwrap_or_default(),
+        token: args.token.unwrap_or_default(),
     };
 
     let mut next_update = Utc::now();
+    let mut previous_board: Option<Board> = None;
+    let mut grief_tracker = GriefTracker::new(config.grief_log_path());
+    // Garde la dernière version valide de chaque pattern si le fichier est cassé pendant l'event
+    let mut pattern_cache = PatternCache::default();
+    let mut load_errors: Vec<Option<String>> = vec![None; patterns.len()];
 
     loop {
-        let mut total_pixels_placed = 0;
-        let mut wait_duration = None;
-
-        for (_, pattern) in patterns.iter().enumerate() {
-            let pattern_content = fs::read_to_string(&pattern.pattern_path).unwrap();
-            let pattern_json: Pattern = serde_json::from_str(&pattern_content)
-                .expect("Couldn't deserilize into");
-
-            if Utc::now() >= next_update {
-                //WARN: this could go wrong if the local time is not sync
-                let now = Local::now();
-                let timestamp = now.format("%Y-%m-%d_%H-%M-%S").to_string();
-
-                let (colors, board) = client.get_board().await?;
-                save_board_state(&colors, &board, &timestamp)?;
-
-                let (defensive1_pixels, def1_wait) = client.process_pattern(
-                    &mut auth,
-                    &pattern_json,
-                    pattern.x,
-                    pattern.y,
-                    &board,
-                    MAX_PIXELS_PER_BATCH
-                ).await?;
-
-                total_pixels_placed += defensive1_pixels;
-                if let Some(duration) = def1_wait {
-                    wait_duration = Some(duration);
+
+        for (i, spec) in patterns.iter().enumerate() {
+            match pattern_cache.refresh(&spec.pattern_path) {
+                Ok(reparsed) => {
+                    if let Some(pattern) = pattern_cache.get(&spec.pattern_path).filter(|_| reparsed) {
+                        if pattern.clipped() > 0 {
+                            let (width, height) = pattern.size();
+                            warn!("Pattern {}: {} pixels outside its {}x{} box were clipped",
+                                spec.name, pattern.clipped(), width, height);
+                        }
+                    }
+                    if load_errors[i].take().is_some() {
+                        info!("Pattern {} is valid again", spec.name);
+                    }
+                }
+                Err(e) => {
+                    // On ne log qu'une fois la même erreur, la boucle tourne toutes les 10 secondes
+                    let message = e.to_string();
+                    if load_errors[i].as_ref() != Some(&message) {
+                        match pattern_cache.get(&spec.pattern_path) {
+                            Some(_) => error!("{} (keeping the previous version)", message),
+                            None => error!("{} (pattern skipped)", message),
+                        }
+                        load_errors[i] = Some(message);
+                    }
                 }
+            }
+        }
+        let loaded: Vec<(&ArgSpecs, &Pattern)> = patterns
+            .iter()
+            .filter_map(|spec| pattern_cache.get(&spec.pattern_path).map(|pattern| (spec, pattern)))
+            .collect();
+        if Utc::now() >= next_update {
+            //WARN: this could go wrong if the local time is not sync
+            let now = Local::now();
+            let timestamp = now.format("%Y-%m-%d_%H-%M-%S").to_string();
 
-                if total_pixels_placed < MAX_PIXELS_PER_BATCH {
-                    continue;
-                } else {
-                    next_update = Utc::now() + if let Some(duration) = wait_duration {
-                        chrono::Duration::from_std(duration)?
-                    } else {
-                        chrono::Duration::minutes(BATCH_DELAY_MINUTES as i64)
-                    };
-                    break;
+            let (colors, board) = client.get_board().await?;
+            if let Some(previous) = &previous_board {
+                if (previous.width(), previous.height()) != (board.width(), board.height()) {
+                    info!("Board size changed from {}x{} to {}x{}",
+                        previous.width(), previous.height(), board.width(), board.height());
+                }
+                let changes = previous.diff(&board);
+                let mut by_user: HashMap<&str, usize> = HashMap::new();
+                for change in &changes {
+                    *by_user.entry(change.info.username_or_unknown()).or_default() += 1;
+                }
+                let mut by_user: Vec<_> = by_user.into_iter().collect();
+                by_user.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+                info!("{} pixels changed since last board fetch", changes.len());
+                for (username, count) in by_user {
+                    debug!("  {} pixels changed by {}", count, username);
                 }
+                // Le journal ne doit pas arrêter le placement en plein event
+                if let Err(e) = grief_tracker.observe(previous, &board, &loaded) {
+                    error!("{:#}", e);
+                }
+            }
+            save_board_state(&config.snapshot_dir, &colors, &board, &timestamp)?;
+
+            // Un seul plan pour tous les patterns, le batch prend les pixels les plus urgents
+            let plan = PlacementPlan::build(&loaded, &board, &colors, Utc::now());
+            plan.log_summary(config.max_pixels_per_batch);
+            let batch: Vec<(i32, i32, u8)> = plan
+                .next_batch(config.max_pixels_per_batch)
+                .iter()
+                .map(|entry| (entry.x, entry.y, entry.color))
+                .collect();
+            previous_board = Some(board);
+
+            if batch.is_empty() {
+                info!("All patterns are complete, next check in {}s", config.retry_delay_secs);
+                next_update = Utc::now() + chrono::Duration::from_std(config.retry_delay())?;
+            } else {
+                let (pixels_placed, pixels_deferred, wait_duration) = client.place_batch(&mut auth, &batch).await?;
+                info!("{} of {} planned pixels placed, {} deferred by the server (too early)",
+                    pixels_placed, batch.len(), pixels_deferred);
+                next_update = Utc::now() + if let Some(duration) = wait_duration {
+                    chrono::Duration::from_std(duration)?
+                } else {
+                    chrono::Duration::minutes(config.batch_delay_minutes as i64)
+                };
             }
+            grief_tracker.end_cycle();
         }
+
         let wait_time = next_update.signed_duration_since(Utc::now());
         if wait_time.num_seconds() > 0 {
             let mins = wait_time.num_minutes();
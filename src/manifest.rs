@@ -0,0 +1,166 @@
+use anyhow::{Result, Context, anyhow};
+use serde::Deserialize;
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+};
+
+use crate::args_parser::{ArgSpecs, PatternOptions};
+
+/// Une entrée `[[pattern]]` du manifest
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct ManifestEntry {
+    name: String,
+    path: String,
+    origin: ManifestOrigin,
+    priority: u32,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+    #[serde(default)]
+    options: PatternOptions,
+}
+
+/// `origin = [x, y]` ou `origin = "auto"`
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ManifestOrigin {
+    At([i32; 2]),
+    Keyword(String),
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "pattern")]
+    patterns: Vec<ManifestEntry>,
+}
+
+/// Charge un manifest TOML ou JSON (choisi selon l'extension) et renvoie les patterns activés.
+///
+/// Les chemins relatifs sont résolus depuis le dossier du manifest.
+pub fn load_manifest(path: &Path) -> Result<Vec<ArgSpecs>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read pattern manifest {}", path.display()))?;
+
+    let manifest: Manifest = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Invalid pattern manifest {}: {}", path.display(), e))?,
+        _ => toml::from_str(&content)
+            .map_err(|e| anyhow!("Invalid pattern manifest {}: {}", path.display(), e))?,
+    };
+
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut names = HashSet::new();
+    let mut specs = Vec::new();
+
+    for entry in manifest.patterns {
+        if !names.insert(entry.name.clone()) {
+            return Err(anyhow!("Duplicate pattern name {:?} in manifest {}", entry.name, path.display()));
+        }
+        if !entry.enabled {
+            continue;
+        }
+
+        let (origin, auto_origin) = match entry.origin {
+            ManifestOrigin::At(origin) => (origin, false),
+            ManifestOrigin::Keyword(keyword) if keyword == "auto" => ([0, 0], true),
+            ManifestOrigin::Keyword(keyword) => {
+                return Err(anyhow!("Pattern {:?} in manifest {}: origin must be [x, y] or \"auto\", got {:?}",
+                    entry.name, path.display(), keyword));
+            }
+        };
+
+        specs.push(ArgSpecs {
+            name: entry.name,
+            pattern_path: base_dir.join(&entry.path).to_string_lossy().into_owned(),
+            x: origin[0],
+            y: origin[1],
+            priority: entry.priority,
+            options: entry.options,
+            auto_origin,
+        });
+    }
+
+    Ok(specs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // Chaque test écrit son manifest dans son propre dossier temporaire
+    fn write_manifest(test: &str, file: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("place_manifest_{}_{}", std::process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn loads_enabled_entries_relative_to_the_manifest() {
+        let path = write_manifest("entries", "patterns.toml", r#"
+[[pattern]]
+name = "core"
+path = "sub dir/core.json"
+origin = [3, 4]
+priority = 1
+
+[pattern.options]
+max_pixels_per_batch = 2
+order = "outline-first"
+
+[[pattern]]
+name = "logo"
+path = "logo.json"
+origin = "auto"
+priority = 2
+
+[[pattern]]
+name = "old"
+path = "old.json"
+origin = [0, 0]
+priority = 3
+enabled = false
+"#);
+        let specs = load_manifest(&path).unwrap();
+        let dir = path.parent().unwrap();
+
+        assert_eq!(specs.len(), 2);
+        assert_eq!((specs[0].name.as_str(), specs[0].x, specs[0].y, specs[0].auto_origin), ("core", 3, 4, false));
+        assert_eq!(PathBuf::from(&specs[0].pattern_path), dir.join("sub dir/core.json"));
+        assert_eq!(specs[0].options.max_pixels_per_batch, Some(2));
+        assert_eq!((specs[1].name.as_str(), specs[1].auto_origin, specs[1].priority), ("logo", true, 2));
+    }
+
+    #[test]
+    fn loads_json_manifests() {
+        let path = write_manifest("json", "patterns.json",
+            r#"{"pattern": [{"name": "core", "path": "core.json", "origin": [1, 2], "priority": 1}]}"#);
+        let specs = load_manifest(&path).unwrap();
+        assert_eq!((specs[0].x, specs[0].y), (1, 2));
+    }
+
+    #[test]
+    fn rejects_duplicates_and_unknown_fields() {
+        let entry = |name: &str, extra: &str| format!(
+            "[[pattern]]\nname = \"{}\"\npath = \"a.json\"\norigin = [0, 0]\npriority = 1\n{}\n", name, extra);
+        let error = |test: &str, content: String| {
+            load_manifest(&write_manifest(test, "patterns.toml", &content)).unwrap_err().to_string()
+        };
+
+        // Un doublon est refusé même si l'une des deux entrées est désactivée
+        let duplicate = entry("core", "enabled = false") + &entry("core", "");
+        assert!(error("duplicate", duplicate).contains("Duplicate pattern name \"core\""));
+        assert!(error("field", entry("core", "colour = 3")).contains("unknown field `colour`"));
+        assert!(error("option", entry("core", "[pattern.options]\nwieght = 2")).contains("unknown field `wieght`"));
+        assert!(error("origin", entry("core", "").replace("[0, 0]", "\"center\"")).contains("origin must be [x, y] or \"auto\""));
+    }
+}
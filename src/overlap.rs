@@ -0,0 +1,257 @@
+use anyhow::{Result, anyhow};
+use log::{debug, info, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use crate::args_parser::{ArgSpecs, OverlapsArgs};
+use crate::board::Board;
+use crate::palette::Palette;
+use crate::pattern::{load_patterns, Pattern, PatternPixel, PixelColor};
+use crate::planner::build_target_map;
+use crate::render::render_pattern;
+
+/// Cases communes à deux patterns, du point de vue de celui qui les garde
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Overlap {
+    /// Index du pattern prioritaire, qui garde les cases
+    pub winner: usize,
+    pub loser: usize,
+    pub cells: usize,
+    /// Cases où la couleur posée par le gagnant ne convient pas au perdant
+    pub conflicts: usize,
+    /// Coins de la zone commune, en coordonnées de la board
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+/// Chevauchements entre les patterns actifs, une fois les priorités appliquées
+#[derive(Debug, Clone)]
+pub struct OverlapReport {
+    names: Vec<String>,
+    priorities: Vec<u32>,
+    /// Cases du dessin de chaque pattern sur la board
+    sizes: Vec<usize>,
+    /// Cases en conflit perdues par chaque pattern
+    lost: Vec<usize>,
+    overlaps: Vec<Overlap>,
+}
+
+/// Compare chaque case avec son gagnant, comme `build_target_map` : les patterns sont donnés
+/// du plus prioritaire au moins prioritaire.
+pub fn analyze(patterns: &[(&ArgSpecs, &Pattern)], board: &Board) -> OverlapReport {
+    let mut sizes = vec![0; patterns.len()];
+    let mut winners: HashMap<(i32, i32), (usize, &PixelColor)> = HashMap::new();
+    let mut pairs: HashMap<(usize, usize), Overlap> = HashMap::new();
+    let mut lost = vec![0; patterns.len()];
+
+    for (index, (spec, pattern)) in patterns.iter().enumerate() {
+        // Pixel en double dans le même pattern : la validation le signale déjà, seule la première copie compte
+        let mut seen = HashSet::new();
+        for p in pattern.pixels() {
+            let (x, y) = (spec.x + p.x, spec.y + p.y);
+            if !board.contains(x, y) || !seen.insert((x, y)) {
+                continue;
+            }
+            sizes[index] += 1;
+            let Some(&(winner, color)) = winners.get(&(x, y)) else {
+                winners.insert((x, y), (index, &p.color));
+                continue;
+            };
+
+            // "any" chez le gagnant n'est jamais réparé, le perdant n'est donc pas gêné
+            let conflict = color.preferred().is_some_and(|id| !p.color.accepts(id));
+            let overlap = pairs.entry((winner, index)).or_insert(Overlap {
+                winner,
+                loser: index,
+                cells: 0,
+                conflicts: 0,
+                min: (x, y),
+                max: (x, y),
+            });
+            overlap.cells += 1;
+            overlap.min = (overlap.min.0.min(x), overlap.min.1.min(y));
+            overlap.max = (overlap.max.0.max(x), overlap.max.1.max(y));
+            if conflict {
+                overlap.conflicts += 1;
+                lost[index] += 1;
+            }
+        }
+    }
+
+    let mut overlaps: Vec<Overlap> = pairs.into_values().collect();
+    overlaps.sort_by_key(|o| (o.winner, o.loser));
+    OverlapReport {
+        names: patterns.iter().map(|(spec, _)| spec.name.clone()).collect(),
+        priorities: patterns.iter().map(|(spec, _)| spec.priority).collect(),
+        sizes,
+        lost,
+        overlaps,
+    }
+}
+
+impl OverlapReport {
+    #[cfg(test)]
+    pub fn overlaps(&self) -> &[Overlap] {
+        &self.overlaps
+    }
+
+    /// Nombre de cases en conflit perdues par le pattern `index`
+    #[cfg(test)]
+    pub fn lost(&self, index: usize) -> usize {
+        self.lost[index]
+    }
+
+    /// Une ligne par paire de patterns qui se chevauchent, `true` quand les couleurs s'opposent
+    fn overlap_lines(&self) -> Vec<(bool, String)> {
+        self.overlaps
+            .iter()
+            .map(|o| {
+                let (winner, loser) = (&self.names[o.winner], &self.names[o.loser]);
+                let reason = if self.priorities[o.winner] == self.priorities[o.loser] {
+                    format!("same priority {}, declared first", self.priorities[o.winner])
+                } else {
+                    format!("priority {} over {}", self.priorities[o.winner], self.priorities[o.loser])
+                };
+                let colors = if o.conflicts > 0 {
+                    format!("{} with different colors", o.conflicts)
+                } else {
+                    "colors agree".to_string()
+                };
+                let line = format!("{} and {} overlap on {} cells from ({}, {}) to ({}, {}), {}: {} wins ({})",
+                    winner, loser, o.cells, o.min.0, o.min.1, o.max.0, o.max.1, colors, winner, reason);
+                (o.conflicts > 0, line)
+            })
+            .collect()
+    }
+
+    /// Une ligne par pattern qui perd des cases
+    fn loss_lines(&self) -> Vec<String> {
+        (0..self.names.len())
+            .filter(|&i| self.lost[i] > 0)
+            .map(|i| format!("{} loses {} of its {} cells to higher priority patterns",
+                self.names[i], self.lost[i], self.sizes[i]))
+            .collect()
+    }
+
+    /// Au démarrage : les conflits en warning, les chevauchements sans conséquence en info
+    pub fn log(&self) {
+        if self.overlaps.is_empty() {
+            debug!("No overlap between patterns");
+            return;
+        }
+        for (conflict, line) in self.overlap_lines() {
+            if conflict {
+                warn!("{}", line);
+            } else {
+                info!("{}", line);
+            }
+        }
+        for line in self.loss_lines() {
+            warn!("{}", line);
+        }
+    }
+}
+
+/// Cible fusionnée de tous les patterns, en coordonnées de la board et de la taille de la board
+pub fn composite(patterns: &[(&ArgSpecs, &Pattern)], board: &Board) -> Pattern {
+    let mut cells: Vec<_> = build_target_map(patterns, board).into_iter().collect();
+    cells.sort_by_key(|&((x, y), _)| (y, x));
+    let pixels = cells
+        .into_iter()
+        .map(|((x, y), target)| PatternPixel {
+            x,
+            y,
+            color: target.color,
+            weight: (target.weight != 1).then_some(target.weight),
+        })
+        .collect();
+    Pattern::new(board.width() as u32, board.height() as u32, pixels)
+}
+
+pub fn run(args: &OverlapsArgs, specs: &[ArgSpecs], board: &Board, snapshot_dir: &Path) -> Result<()> {
+    if args.zoom == 0 {
+        return Err(anyhow!("--zoom must be at least 1"));
+    }
+
+    let patterns = load_patterns(specs)?;
+    let loaded: Vec<(&ArgSpecs, &Pattern)> = specs.iter().zip(&patterns).collect();
+
+    let report = analyze(&loaded, board);
+    let lines = report.overlap_lines();
+    if lines.is_empty() {
+        println!("No overlap between the {} patterns", specs.len());
+    }
+    for (_, line) in lines {
+        println!("{}", line);
+    }
+    for line in report.loss_lines() {
+        println!("{}", line);
+    }
+
+    if args.png.is_none() && args.json.is_none() {
+        return Ok(());
+    }
+    let target = composite(&loaded, board);
+    if let Some(path) = &args.json {
+        target.save(path)?;
+        info!("Composite target with {} pixels written to {}", target.pixels().count(), path.display());
+    }
+    if let Some(path) = &args.png {
+        let palette = Palette::resolve(args.palette.as_deref(), snapshot_dir)?;
+        let img = render_pattern(&target, &palette, args.zoom, false)?;
+        img.save(path)?;
+        info!("Composite target {}x{} written to {}", img.width(), img.height(), path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args_parser::PatternOptions;
+    use crate::board::Orientation;
+    use crate::test_utils::{exact_row, row, spec};
+
+    #[test]
+    fn reports_conflicts_and_cells_lost() {
+        use PixelColor::*;
+        let board = Board::from_matrix(1, 6, Orientation::None, |_, _| 1);
+        // a couvre x = 0..4 ; b couvre x = 2..6 et n'est d'accord que sur la case 3
+        let a = row(&[Exact(2), Exact(2), Exact(2), Exact(3)]);
+        let b = row(&[Exact(5), OneOf(vec![5, 3]), Any, Exact(5)]);
+        let specs = [spec("a", 0, 0, 1, PatternOptions::default()), spec("b", 2, 0, 2, PatternOptions::default())];
+        let patterns = [(&specs[0], &a), (&specs[1], &b)];
+
+        let report = analyze(&patterns, &board);
+        assert_eq!(report.overlaps(), [Overlap { winner: 0, loser: 1, cells: 2, conflicts: 1, min: (2, 0), max: (3, 0) }]);
+        assert_eq!((report.lost(0), report.lost(1)), (0, 1));
+
+        let target = composite(&patterns, &board);
+        let colors: Vec<(i32, PixelColor)> = target.pixels().map(|p| (p.x, p.color.clone())).collect();
+        assert_eq!(colors, vec![(0, Exact(2)), (1, Exact(2)), (2, Exact(2)), (3, Exact(3)), (4, Any), (5, Exact(5))]);
+    }
+
+    #[test]
+    fn duplicated_pixels_are_not_an_overlap() {
+        let board = Board::from_matrix(1, 4, Orientation::None, |_, _| 1);
+        let single = exact_row(&[2, 3]);
+        // (0, 0) listé deux fois, dans le gagnant comme dans le perdant
+        let doubled = |color: u8| {
+            let row = exact_row(&[color, color]);
+            Pattern::new(2, 1, row.pixels().chain(row.pixels().take(1)).cloned().collect())
+        };
+        let specs = [spec("a", 0, 0, 1, PatternOptions::default()), spec("b", 0, 0, 2, PatternOptions::default())];
+
+        let report = analyze(&[(&specs[0], &doubled(2))], &board);
+        assert!(report.overlaps().is_empty());
+        assert_eq!(report.sizes, [2]);
+
+        let loser = doubled(5);
+        let report = analyze(&[(&specs[0], &single), (&specs[1], &loser)], &board);
+        assert_eq!(report.overlaps(), [Overlap { winner: 0, loser: 1, cells: 2, conflicts: 2, min: (0, 0), max: (1, 0) }]);
+        assert_eq!(report.sizes, [2, 2]);
+        assert_eq!(report.lost(1), 2);
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::{Result, Context, anyhow};
+use clap::ValueEnum;
+use log::info;
+use std::{fs, path::Path};
+
+use crate::colors_file;
+use crate::config::latest_snapshot_file;
+use crate::place_client::Color;
+
+/// Palette fournie avec le client, au même format que les `colors_<timestamp>.txt` du dossier `map`
+const BUNDLED_COLORS: &str = include_str!("../colors.txt");
+
+/// Distance utilisée pour choisir la couleur de la palette la plus proche
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorDistance {
+    /// Distance euclidienne en RGB
+    #[default]
+    Rgb,
+    /// Distance euclidienne en CIELAB (CIE76)
+    Lab,
+    /// CIEDE2000, la plus proche de la perception mais la plus lente
+    Ciede2000,
+}
+
+/// Couleurs du serveur, triées par id
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Vec<Color>,
+    // Couleurs converties en CIELAB, dans le même ordre que `colors`
+    lab: Vec<[f32; 3]>,
+}
+
+impl Palette {
+    pub fn bundled() -> Self {
+        Palette::parse(BUNDLED_COLORS).expect("bundled colors.txt is valid")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read palette {}", path.display()))?;
+        Palette::parse(&content)
+            .with_context(|| format!("Invalid palette {}", path.display()))
+    }
+
+    /// Palette du fichier donné, ou celle fournie avec le client
+    pub fn load_or_bundled(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Palette::load(path),
+            None => Ok(Palette::bundled()),
+        }
+    }
+
+    /// Lit des lignes au format "Color 7: orange (RGB: 230,126,34)"
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut colors: Vec<Color> = colors_file::parse(content)?
+            .into_iter()
+            .map(|line| Color {
+                id: line.id,
+                name: line.name,
+                red: line.rgb[0],
+                green: line.rgb[1],
+                blue: line.rgb[2],
+            })
+            .collect();
+
+        if colors.is_empty() {
+            return Err(anyhow!("Empty palette"));
+        }
+        colors.sort_by_key(|c| c.id);
+        let lab = colors
+            .iter()
+            .map(|c| rgb_to_lab([c.red as f32, c.green as f32, c.blue as f32]))
+            .collect();
+        Ok(Palette { colors, lab })
+    }
+
+    /// Palette de `--palette`, sinon celle du dernier fetch de la board, sinon celle fournie avec le client
+    pub fn resolve(path: Option<&Path>, snapshot_dir: &Path) -> Result<Self> {
+        if let Some(path) = path {
+            return Palette::load(path);
+        }
+        Ok(Palette::latest_snapshot(snapshot_dir).unwrap_or_else(|| {
+            info!("No board snapshot in {}, using the bundled palette", snapshot_dir.display());
+            Palette::bundled()
+        }))
+    }
+
+    /// Palette du dernier fetch de la board (le `colors_<timestamp>.txt` le plus récent de `snapshot_dir`)
+    pub fn latest_snapshot(snapshot_dir: &Path) -> Option<Self> {
+        let latest = latest_snapshot_file(snapshot_dir, "colors")?;
+        Palette::load(&latest).ok()
+    }
+
+    /// Ids de toutes les couleurs de la palette
+    pub fn ids(&self) -> impl Iterator<Item = u8> + '_ {
+        self.colors.iter().map(|c| c.id)
+    }
+
+    pub fn get(&self, id: u8) -> Option<&Color> {
+        self.colors.iter().find(|c| c.id == id)
+    }
+
+    /// Couleur par nom, sans tenir compte de la casse
+    pub fn find_by_name(&self, name: &str) -> Option<&Color> {
+        self.colors.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Couleur de la palette la plus proche de `rgb` (composantes entre 0 et 255)
+    pub fn nearest(&self, rgb: [f32; 3], distance: ColorDistance) -> &Color {
+        let lab = match distance {
+            ColorDistance::Rgb => [0.0; 3],
+            ColorDistance::Lab | ColorDistance::Ciede2000 => rgb_to_lab(rgb),
+        };
+
+        let score = |i: usize| -> f32 {
+            let c = &self.colors[i];
+            match distance {
+                ColorDistance::Rgb => {
+                    let dr = c.red as f32 - rgb[0];
+                    let dg = c.green as f32 - rgb[1];
+                    let db = c.blue as f32 - rgb[2];
+                    dr * dr + dg * dg + db * db
+                }
+                ColorDistance::Lab => {
+                    let [dl, da, db] = [0, 1, 2].map(|k| self.lab[i][k] - lab[k]);
+                    dl * dl + da * da + db * db
+                }
+                ColorDistance::Ciede2000 => ciede2000(self.lab[i], lab),
+            }
+        };
+
+        let best = (0..self.colors.len())
+            .min_by(|&a, &b| score(a).total_cmp(&score(b)))
+            .expect("palette is never empty");
+        &self.colors[best]
+    }
+}
+
+/// Différence de couleur perçue (CIEDE2000) entre deux couleurs du serveur
+pub fn perceptual_distance(a: &Color, b: &Color) -> f32 {
+    let lab = |c: &Color| rgb_to_lab([c.red as f32, c.green as f32, c.blue as f32]);
+    ciede2000(lab(a), lab(b))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    let c = (c / 255.0).clamp(0.0, 1.0);
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// sRGB (0-255) vers CIELAB, illuminant D65
+fn rgb_to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(srgb_to_linear);
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    let f = |t: f32| {
+        const DELTA: f32 = 6.0 / 29.0;
+        if t > DELTA * DELTA * DELTA {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Différence de couleur CIEDE2000 entre deux couleurs CIELAB
+fn ciede2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let [l1, a1, b1] = lab1.map(f64::from);
+    let [l2, a2, b2] = lab2.map(f64::from);
+    let pow7 = |v: f64| v.powi(7);
+    let pow25_7 = pow7(25.0);
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - (pow7(c_bar) / (pow7(c_bar) + pow25_7)).sqrt());
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = (a1p * a1p + b1 * b1).sqrt();
+    let c2p = (a2p * a2p + b2 * b2).sqrt();
+
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1p = hue(b1, a1p);
+    let h2p = hue(b2, a2p);
+
+    let delta_lp = l2 - l1;
+    let delta_cp = c2p - c1p;
+    let delta_hp = if c1p * c2p == 0.0 {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p - h1p > 180.0 {
+        h2p - h1p - 360.0
+    } else {
+        h2p - h1p + 360.0
+    };
+    let delta_big_hp = 2.0 * (c1p * c2p).sqrt() * (delta_hp.to_radians() / 2.0).sin();
+
+    let l_bar_p = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar_p = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let cos_deg = |deg: f64| deg.to_radians().cos();
+    let t = 1.0 - 0.17 * cos_deg(h_bar_p - 30.0)
+        + 0.24 * cos_deg(2.0 * h_bar_p)
+        + 0.32 * cos_deg(3.0 * h_bar_p + 6.0)
+        - 0.20 * cos_deg(4.0 * h_bar_p - 63.0);
+    let delta_theta = 30.0 * (-((h_bar_p - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (pow7(c_bar_p) / (pow7(c_bar_p) + pow25_7)).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar_p - 50.0).powi(2) / (20.0 + (l_bar_p - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (dl, dc, dh) = (delta_lp / s_l, delta_cp / s_c, delta_big_hp / s_h);
+    (dl * dl + dc * dc + dh * dh + r_t * dc * dh).sqrt() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ciede2000_matches_reference_pairs() {
+        // Paires de test publiées par Sharma, Wu et Dalal (2005)
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, -1.3802, -84.2814], [50.0, 0.0, -82.7485], 1.0000),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+        ];
+        for (lab1, lab2, expected) in pairs {
+            let delta = ciede2000(lab1, lab2);
+            assert!((delta - expected).abs() < 1e-3, "{:?} {:?}: {} != {}", lab1, lab2, delta, expected);
+        }
+    }
+
+    #[test]
+    fn nearest_picks_exact_palette_colors() {
+        let palette = Palette::bundled();
+        for distance in [ColorDistance::Rgb, ColorDistance::Lab, ColorDistance::Ciede2000] {
+            for color in &palette.colors {
+                let rgb = [color.red as f32, color.green as f32, color.blue as f32];
+                assert_eq!(palette.nearest(rgb, distance).id, color.id);
+            }
+        }
+    }
+}
@@ -0,0 +1,392 @@
+use anyhow::{Result, anyhow};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt, fs,
+    hash::{Hash, Hasher},
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::args_parser::ArgSpecs;
+use crate::ascii::{self, GridEncoding};
+
+/// Contenu brut d'un fichier pattern : `{"width": .., "height": .., "pattern": [{"x": .., "y": .., "color": ..}]}`
+#[derive(Deserialize, Debug, Clone)]
+pub struct PatternFile {
+    #[serde(default)]
+    pub width: Option<u32>,
+    #[serde(default)]
+    pub height: Option<u32>,
+    pub pattern: Vec<PatternPixel>,
+}
+
+impl PatternFile {
+    /// Zone couverte par les pixels en partant de (0, 0), calculée en `i64` : `i32::MAX + 1` tient dans un `u32`
+    pub fn extent(&self) -> (u32, u32) {
+        let extent = |coordinate: fn(&PatternPixel) -> i32| {
+            self.pattern.iter().map(|p| coordinate(p) as i64 + 1).max().unwrap_or(0).max(0) as u32
+        };
+        (extent(|p| p.x), extent(|p| p.y))
+    }
+
+    /// Boîte du pattern : `width`/`height` du fichier, ou à défaut la zone couverte par ses pixels
+    pub fn bounding_box(&self) -> (u32, u32) {
+        let (max_x, max_y) = self.extent();
+        (self.width.unwrap_or(max_x), self.height.unwrap_or(max_y))
+    }
+}
+
+/// Pattern chargé, dont tous les pixels sont dans sa boîte `width` x `height`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(from = "PatternFile")]
+pub struct Pattern {
+    width: u32,
+    height: u32,
+    pattern: Vec<PatternPixel>,
+    // Pixels du fichier retirés car hors de la boîte (coordonnées négatives comprises)
+    #[serde(skip)]
+    clipped: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PatternPixel {
+    pub x: i32,
+    pub y: i32,
+    pub color: PixelColor,
+    /// Importance du pixel dans son pattern (1 par défaut) : les plus lourds sont réparés en premier
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+}
+
+/// Couleur attendue d'un pixel du pattern.
+///
+/// Dans le fichier : `4` pour une couleur précise, `[4, 6]` pour l'une des couleurs de la liste
+/// (la première est posée en cas de réparation) et `"any"` pour un pixel qui fait partie
+/// du pattern mais dont la couleur importe peu.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "RawPixelColor", into = "RawPixelColor")]
+pub enum PixelColor {
+    Exact(u8),
+    OneOf(Vec<u8>),
+    Any,
+}
+
+// Forme JSON de PixelColor
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum RawPixelColor {
+    Id(u8),
+    List(Vec<u8>),
+    Keyword(String),
+}
+
+impl TryFrom<RawPixelColor> for PixelColor {
+    type Error = String;
+
+    fn try_from(raw: RawPixelColor) -> Result<Self, Self::Error> {
+        match raw {
+            RawPixelColor::Id(id) => Ok(PixelColor::Exact(id)),
+            RawPixelColor::List(ids) if ids.is_empty() => Err("color list must not be empty".to_string()),
+            RawPixelColor::List(ids) => Ok(PixelColor::OneOf(ids)),
+            RawPixelColor::Keyword(keyword) if keyword == "any" => Ok(PixelColor::Any),
+            RawPixelColor::Keyword(keyword) => Err(format!("unknown color {:?}, expected an id, a list of ids or \"any\"", keyword)),
+        }
+    }
+}
+
+impl From<PixelColor> for RawPixelColor {
+    fn from(color: PixelColor) -> Self {
+        match color {
+            PixelColor::Exact(id) => RawPixelColor::Id(id),
+            PixelColor::OneOf(ids) => RawPixelColor::List(ids),
+            PixelColor::Any => RawPixelColor::Keyword("any".to_string()),
+        }
+    }
+}
+
+impl PixelColor {
+    /// La couleur actuelle de la board convient-elle ?
+    pub fn accepts(&self, color: u8) -> bool {
+        match self {
+            PixelColor::Exact(id) => *id == color,
+            PixelColor::OneOf(ids) => ids.contains(&color),
+            PixelColor::Any => true,
+        }
+    }
+
+    /// Couleur posée quand le pixel doit être réparé, `None` pour `"any"`
+    pub fn preferred(&self) -> Option<u8> {
+        match self {
+            PixelColor::Exact(id) => Some(*id),
+            PixelColor::OneOf(ids) => ids.first().copied(),
+            PixelColor::Any => None,
+        }
+    }
+
+    /// Ids de couleur mentionnés, pour les vérifier contre la palette
+    pub fn ids(&self) -> &[u8] {
+        match self {
+            PixelColor::Exact(id) => std::slice::from_ref(id),
+            PixelColor::OneOf(ids) => ids,
+            PixelColor::Any => &[],
+        }
+    }
+}
+
+impl fmt::Display for PixelColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PixelColor::Exact(id) => write!(f, "{}", id),
+            PixelColor::OneOf(ids) => write!(f, "one of {:?}", ids),
+            PixelColor::Any => write!(f, "any"),
+        }
+    }
+}
+
+impl PatternPixel {
+    pub fn in_box(&self, width: u32, height: u32) -> bool {
+        self.x >= 0 && self.y >= 0 && (self.x as u32) < width && (self.y as u32) < height
+    }
+
+    pub fn weight(&self) -> u32 {
+        self.weight.unwrap_or(1)
+    }
+}
+
+impl From<PatternFile> for Pattern {
+    fn from(file: PatternFile) -> Self {
+        let (width, height) = file.bounding_box();
+        let total = file.pattern.len();
+        let pattern: Vec<PatternPixel> = file.pattern
+            .into_iter()
+            .filter(|p| p.in_box(width, height))
+            .collect();
+        Pattern {
+            width,
+            height,
+            clipped: total - pattern.len(),
+            pattern,
+        }
+    }
+}
+
+impl Pattern {
+    pub fn new(width: u32, height: u32, pixels: Vec<PatternPixel>) -> Self {
+        Pattern::from(PatternFile {
+            width: Some(width),
+            height: Some(height),
+            pattern: pixels,
+        })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Couldn't read pattern {}: {}", path, e))?;
+        Pattern::parse(path, &content)
+    }
+
+    /// Parse le contenu du fichier `path`, dans le format donné par son extension
+    fn parse(path: &str, content: &str) -> Result<Self> {
+        if let Some(encoding) = GridEncoding::from_path(Path::new(path)) {
+            return ascii::parse(content, encoding)
+                .map(|(file, _)| Pattern::from(file))
+                .map_err(|e| anyhow!("Couldn't parse pattern {}: {}", path, e));
+        }
+        serde_json::from_str(content)
+            .map_err(|e| anyhow!("Couldn't parse pattern {}: {}", path, e))
+    }
+
+    /// Écrit le pattern, au format texte si l'extension est `.txt` ou `.rle`, en JSON sinon
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = if let Some(encoding) = GridEncoding::from_path(path) {
+            if self.pattern.iter().any(|p| p.weight.is_some()) {
+                warn!("Pixel weights can't be stored in {}, save as .json to keep them", path.display());
+            }
+            ascii::write(self, encoding)
+                .map_err(|e| anyhow!("Couldn't write pattern {}: {}", path.display(), e))?
+        } else {
+            serde_json::to_string_pretty(self)? + "\n"
+        };
+        fs::write(path, content)
+            .map_err(|e| anyhow!("Couldn't write pattern {}: {}", path.display(), e))
+    }
+
+    /// Taille `(width, height)` de la boîte du pattern
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Nombre de pixels du fichier ignorés car hors de la boîte
+    pub fn clipped(&self) -> usize {
+        self.clipped
+    }
+
+    /// Pixels du pattern, relatifs à son origine, dans l'ordre du fichier
+    pub fn pixels(&self) -> impl Iterator<Item = &PatternPixel> {
+        self.pattern.iter()
+    }
+}
+
+/// Charge le pattern de chaque spec, dans le même ordre, et s'arrête au premier fichier invalide
+pub fn load_patterns(specs: &[ArgSpecs]) -> Result<Vec<Pattern>> {
+    specs.iter().map(|spec| Pattern::load(&spec.pattern_path)).collect()
+}
+
+/// Patterns déjà parsés, relus seulement quand leur fichier change
+#[derive(Default)]
+pub struct PatternCache {
+    entries: HashMap<String, CachedPattern>,
+}
+
+struct CachedPattern {
+    modified: Option<SystemTime>,
+    hash: u64,
+    /// Dernière version valide du fichier
+    pattern: Option<Pattern>,
+    /// Erreur du contenu actuel, s'il est invalide
+    error: Option<String>,
+}
+
+impl PatternCache {
+    /// Relit le fichier si sa date de modification a changé, puis le reparse si son contenu a changé.
+    ///
+    /// Renvoie `true` si le pattern vient d'être (re)parsé. En cas d'erreur, la dernière version
+    /// valide reste disponible via `get`.
+    pub fn refresh(&mut self, path: &str) -> Result<bool> {
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if let Some(entry) = self.entries.get(path) {
+            if modified.is_some() && entry.modified == modified {
+                return entry.result(false);
+            }
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Couldn't read pattern {}: {}", path, e))?;
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if let Some(entry) = self.entries.get_mut(path) {
+            // Fichier touché sans changement de contenu
+            if entry.hash == hash {
+                entry.modified = modified;
+                return entry.result(false);
+            }
+        }
+
+        let entry = self.entries.entry(path.to_string()).or_insert(CachedPattern {
+            modified,
+            hash,
+            pattern: None,
+            error: None,
+        });
+        entry.modified = modified;
+        entry.hash = hash;
+        match Pattern::parse(path, &content) {
+            Ok(pattern) => {
+                entry.pattern = Some(pattern);
+                entry.error = None;
+                Ok(true)
+            }
+            Err(e) => {
+                entry.error = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Pattern> {
+        self.entries.get(path).and_then(|entry| entry.pattern.as_ref())
+    }
+}
+
+impl CachedPattern {
+    fn result(&self, reparsed: bool) -> Result<bool> {
+        match &self.error {
+            Some(error) => Err(anyhow!("{}", error)),
+            None => Ok(reparsed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(x: i32, y: i32) -> PatternPixel {
+        PatternPixel { x, y, color: PixelColor::Exact(1), weight: None }
+    }
+
+    #[test]
+    fn pixels_outside_the_box_are_clipped() {
+        let pattern = Pattern::new(2, 2, vec![pixel(0, 0), pixel(1, 1), pixel(2, 0), pixel(-1, 0), pixel(0, -3)]);
+        assert_eq!(pattern.size(), (2, 2));
+        assert_eq!(pattern.clipped(), 3);
+        assert_eq!(pattern.pixels().cloned().collect::<Vec<_>>(), vec![pixel(0, 0), pixel(1, 1)]);
+    }
+
+    #[test]
+    fn box_defaults_to_the_pixels_extent() {
+        let pattern: Pattern = serde_json::from_str(r#"{"pattern": [{"x": 3, "y": 1, "color": 2}, {"x": -1, "y": 0, "color": 2}]}"#).unwrap();
+        assert_eq!(pattern.size(), (4, 2));
+        assert_eq!(pattern.clipped(), 1);
+
+        let far = PatternFile { width: None, height: None, pattern: vec![pixel(i32::MAX, 0), pixel(0, i32::MIN)] };
+        assert_eq!(far.extent(), (1 << 31, 1));
+    }
+
+    #[test]
+    fn pixel_colors_round_trip() {
+        let json = r#"[{"x":0,"y":0,"color":4},{"x":1,"y":0,"color":[4,6]},{"x":2,"y":0,"color":"any"}]"#;
+        let pixels: Vec<PatternPixel> = serde_json::from_str(json).unwrap();
+        assert_eq!(pixels[0].color, PixelColor::Exact(4));
+        assert_eq!(pixels[1].color, PixelColor::OneOf(vec![4, 6]));
+        assert_eq!(pixels[2].color, PixelColor::Any);
+        assert_eq!(serde_json::to_string(&pixels).unwrap(), json);
+
+        assert!(serde_json::from_str::<PixelColor>(r#"[]"#).is_err());
+        assert!(serde_json::from_str::<PixelColor>(r#""red""#).is_err());
+    }
+
+    #[test]
+    fn repair_respects_color_rules() {
+        assert!(PixelColor::Any.accepts(9));
+        assert!(PixelColor::OneOf(vec![4, 6]).accepts(6));
+        assert!(!PixelColor::OneOf(vec![4, 6]).accepts(5));
+        assert_eq!(PixelColor::OneOf(vec![4, 6]).preferred(), Some(4));
+        assert_eq!(PixelColor::Any.preferred(), None);
+    }
+
+    #[test]
+    fn cache_reparses_only_changed_files() {
+        let path = std::env::temp_dir().join(format!("place_cache_{}.json", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        // Date explicite pour chaque écriture, la résolution du système de fichiers peut être grossière
+        let write = |content: &str, secs: u64| {
+            fs::write(&path, content).unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs)).unwrap();
+        };
+        let mut cache = PatternCache::default();
+
+        write(r#"{"pattern": [{"x": 0, "y": 0, "color": 1}]}"#, 1);
+        assert!(cache.refresh(path_str).unwrap());
+        assert!(!cache.refresh(path_str).unwrap());
+
+        write(r#"{"pattern": [{"x": 0, "y": 0, "color": 1}]}"#, 2);
+        assert!(!cache.refresh(path_str).unwrap());
+
+        write(r#"{"pattern": ["#, 3);
+        assert!(cache.refresh(path_str).is_err());
+        assert!(cache.refresh(path_str).is_err());
+        assert_eq!(cache.get(path_str).unwrap().pixels().count(), 1);
+
+        write(r#"{"pattern": [{"x": 0, "y": 0, "color": 1}, {"x": 1, "y": 0, "color": 2}]}"#, 4);
+        assert!(cache.refresh(path_str).unwrap());
+        assert_eq!(cache.get(path_str).unwrap().pixels().count(), 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,16 +1,16 @@
 use reqwest::Client;
 use anyhow::{Result, anyhow};
-use log::{info, error, debug};
+use log::{info, warn, error, debug};
 use std::collections::HashMap;
 use std::time::Duration;
 use serde::{Deserialize, Serialize};
 use tokio::time::sleep;
 use chrono::Utc;
-use crate::{
-    BATCH_DELAY_MINUTES,
-    MAX_RETRIES,
-    RETRY_DELAY,
-    BOARD_SIZE,
+use crate::board::{Board, Orientation, PixelInfo};
+use crate::config::{
+    DEFAULT_BATCH_DELAY_MINUTES,
+    DEFAULT_MAX_RETRIES,
+    DEFAULT_RETRY_DELAY_SECS,
 };
 
 #[derive(Deserialize, Debug)]
@@ -19,21 +19,51 @@ struct TimerResponse {
     message: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Color {
-    id: u8,
+    pub(crate) id: u8,
     pub(crate) name: String,
     pub(crate) red: u8,
     pub(crate) green: u8,
     pub(crate) blue: u8,
 }
 
+pub const DEFAULT_BASE_URL: &str = "https://ftplace.42lwatch.ch";
+pub const DEFAULT_BOARD_ENDPOINT: &str = "/api/get?type=board";
+pub const DEFAULT_SET_ENDPOINT: &str = "/api/set";
+
 pub struct PlaceClient {
     client: Client,
     base_url: String,
+    board_endpoint: String,
+    set_endpoint: String,
+    max_retries: u32,
+    retry_delay: Duration,
+    batch_delay: Duration,
+    orientation: Orientation,
+}
+
+pub struct PlaceClientBuilder {
+    base_url: String,
+    board_endpoint: String,
+    set_endpoint: String,
+    max_retries: u32,
+    retry_delay: Duration,
+    batch_delay: Duration,
+    orientation: Orientation,
+}
+
+/// Réponse du serveur à un pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlaceOutcome {
+    /// Pixel posé, avec le temps d'attente annoncé s'il y en a un
+    Placed(Option<Duration>),
+    /// "Too early" : le pixel n'est pas posé, il faut attendre
+    TooEarly(Duration),
+    /// Les tokens ont été renouvelés, il faut renvoyer la requête
+    RefreshTokens,
 }
 
-#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct Pixel {
     username: String,
@@ -63,31 +93,81 @@ pub struct Auth {
     pub(crate) token: String,
 }
 
-#[derive(Deserialize, Debug)]
-pub struct Pattern {
-    pattern: Vec<PatternPixel>,
+impl Default for PlaceClientBuilder {
+    fn default() -> Self {
+        PlaceClientBuilder {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            board_endpoint: DEFAULT_BOARD_ENDPOINT.to_string(),
+            set_endpoint: DEFAULT_SET_ENDPOINT.to_string(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: Duration::from_secs(DEFAULT_RETRY_DELAY_SECS),
+            batch_delay: Duration::from_secs(DEFAULT_BATCH_DELAY_MINUTES * 60),
+            orientation: Orientation::default(),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct PatternPixel {
-    x: i32,
-    y: i32,
-    color: u8,
-}
+impl PlaceClientBuilder {
+    pub(crate) fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
 
-impl PlaceClient {
-    pub(crate) fn new() -> Result<Self> {
+    pub(crate) fn board_endpoint(mut self, endpoint: &str) -> Self {
+        self.board_endpoint = endpoint.to_string();
+        self
+    }
+
+    pub(crate) fn set_endpoint(mut self, endpoint: &str) -> Self {
+        self.set_endpoint = endpoint.to_string();
+        self
+    }
+
+    pub(crate) fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub(crate) fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    pub(crate) fn batch_delay(mut self, batch_delay: Duration) -> Self {
+        self.batch_delay = batch_delay;
+        self
+    }
+
+    pub(crate) fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = orientation;
+        self
+    }
+
+    /// L'URL et les endpoints sont vérifiés par `Config::validate_settings`
+    pub(crate) fn build(self) -> PlaceClient {
         let client = Client::new();
-        info!("HTTP client initialized successfully");
+        info!("HTTP client initialized successfully for {}", self.base_url);
 
-        Ok(PlaceClient {
+        PlaceClient {
             client,
-            base_url: "https://ftplace.42lwatch.ch".to_string(),
-        })
+            base_url: self.base_url,
+            board_endpoint: self.board_endpoint,
+            set_endpoint: self.set_endpoint,
+            max_retries: self.max_retries,
+            retry_delay: self.retry_delay,
+            batch_delay: self.batch_delay,
+            orientation: self.orientation,
+        }
+    }
+}
+
+impl PlaceClient {
+    pub(crate) fn builder() -> PlaceClientBuilder {
+        PlaceClientBuilder::default()
     }
 
-    pub(crate) async fn get_board(&self) -> Result<(HashMap<u8, Color>, Vec<Vec<u8>>)> {
-        let url = format!("{}/api/get?type=board", self.base_url);
+    async fn fetch_board_data(&self) -> Result<BoardResponse> {
+        let url = format!("{}{}", self.base_url, self.board_endpoint);
         let mut retries = 0;
 
         loop {
@@ -98,15 +178,15 @@ impl PlaceClient {
                     debug!("Response status: {}", response.status());
 
                     if response.status() == reqwest::StatusCode::BAD_GATEWAY {
-                        if retries >= MAX_RETRIES {
-                            error!("Max retries ({}) reached for 502 error, stopping script", MAX_RETRIES);
-                            return Err(anyhow!("Failed to connect after {} retries", MAX_RETRIES));
+                        if retries >= self.max_retries {
+                            error!("Max retries ({}) reached for 502 error, stopping script", self.max_retries);
+                            return Err(anyhow!("Failed to connect after {} retries", self.max_retries));
                         }
 
                         retries += 1;
                         info!("Received 502 Bad Gateway (attempt {}/{}), waiting {} seconds before retry", 
-                            retries, MAX_RETRIES, RETRY_DELAY.as_secs());
-                        sleep(RETRY_DELAY).await;
+                            retries, self.max_retries, self.retry_delay.as_secs());
+                        sleep(self.retry_delay).await;
                         continue;
                     }
 
@@ -114,56 +194,78 @@ impl PlaceClient {
                         return Err(anyhow!("Request failed with status: {}", response.status()));
                     }
 
-                    let board_data: BoardResponse = response.json().await?;
-                    
-                    let colors: HashMap<u8, Color> = board_data.colors
-                        .into_iter()
-                        .map(|c| (c.id, c))
-                        .collect();
-                    
-                    debug!("Loaded {} color definitions", colors.len());
-
-                    let mut board_matrix = vec![vec![0u8; BOARD_SIZE]; BOARD_SIZE];
-                    
-                    for (y, row) in board_data.board.iter().enumerate() {
-                        for (x, pixel) in row.iter().enumerate() {
-                            board_matrix[y][x] = pixel.color_id;
-                        }
-                    }
-
-                    let mut rotated_matrix = vec![vec![0u8; BOARD_SIZE]; BOARD_SIZE];
-                    for y in 0..BOARD_SIZE {
-                        for x in 0..BOARD_SIZE {
-                            rotated_matrix[x][BOARD_SIZE - 1 - y] = board_matrix[y][x];
-                        }
-                    }
-
-                    let mut final_matrix = vec![vec![0u8; BOARD_SIZE]; BOARD_SIZE];
-                    for y in 0..BOARD_SIZE {
-                        for x in 0..BOARD_SIZE {
-                            final_matrix[y][BOARD_SIZE - 1 - x] = rotated_matrix[y][x];
-                        }  
-                    }
-
-                    info!("Board matrix constructed successfully");
-                    return Ok((colors, final_matrix));
+                    return Ok(response.json().await?);
                 },
                 Err(e) => {
-                    if retries >= MAX_RETRIES {
-                        error!("Max retries ({}) reached for connection error, stopping script", MAX_RETRIES);
-                        return Err(anyhow!("Failed to connect after {} retries: {}", MAX_RETRIES, e));
+                    if retries >= self.max_retries {
+                        error!("Max retries ({}) reached for connection error, stopping script", self.max_retries);
+                        return Err(anyhow!("Failed to connect after {} retries: {}", self.max_retries, e));
                     }
 
                     retries += 1;
-                    error!("Connection error (attempt {}/{}): {}", retries, MAX_RETRIES, e);
-                    info!("Waiting {} seconds before retry", RETRY_DELAY.as_secs());
-                    sleep(RETRY_DELAY).await;
+                    error!("Connection error (attempt {}/{}): {}", retries, self.max_retries, e);
+                    info!("Waiting {} seconds before retry", self.retry_delay.as_secs());
+                    sleep(self.retry_delay).await;
                     continue;
                 }
             }
         }
     }
 
+    fn build_board(matrix: &[Vec<Pixel>], orientation: Orientation) -> Result<Board> {
+        // Dimensions de la matrice du serveur : `rows` lignes de `cols` pixels
+        let rows = matrix.len();
+        let cols = matrix.iter().map(|row| row.len()).max().unwrap_or(0);
+        if rows == 0 || cols == 0 {
+            return Err(anyhow!("Server returned an empty board"));
+        }
+        if matrix.iter().any(|row| row.len() != cols) {
+            warn!("Server board rows have different lengths, missing pixels are filled with color 0");
+        }
+
+        Ok(Board::from_matrix_with_info(rows, cols, orientation, |i, j| {
+            matrix[i].get(j).map_or((0, PixelInfo::default()), |pixel| {
+                (pixel.color_id, PixelInfo::new(&pixel.username, &pixel.set_time))
+            })
+        }))
+    }
+
+    pub(crate) async fn get_board(&self) -> Result<(HashMap<u8, Color>, Board)> {
+        let board_data = self.fetch_board_data().await?;
+        let board = Self::build_board(&board_data.board, self.orientation)?;
+
+        let colors: HashMap<u8, Color> = board_data.colors
+            .into_iter()
+            .map(|c| (c.id, c))
+            .collect();
+        
+        debug!("Loaded {} color definitions", colors.len());
+
+        info!("Board matrix constructed successfully ({}x{}, orientation {})",
+            board.width(), board.height(), self.orientation.name());
+        Ok((colors, board))
+    }
+
+    /// Vérifie qu'un pixel connu, en coordonnées du serveur, a la bonne couleur sur la board déjà récupérée
+    pub(crate) fn check_orientation(&self, board: &Board, x: i32, y: i32, color: u8) -> Result<()> {
+        if board.get(x, y) == Some(color) {
+            info!("Orientation check passed: pixel ({}, {}) has color {} with orientation {}",
+                x, y, color, self.orientation.name());
+            return Ok(());
+        }
+
+        let matching: Vec<String> = board
+            .orientations_matching(self.orientation, x, y, color)
+            .into_iter()
+            .map(Orientation::name)
+            .collect();
+        Err(anyhow!(
+            "Orientation check failed: pixel ({}, {}) should have color {} but has {:?} with orientation {}. \
+             Orientations matching this pixel: [{}]",
+            x, y, color, board.get(x, y), self.orientation.name(), matching.join(", ")
+        ))
+    }
+
     fn calculate_wait_interval(&self, response: &str) -> Result<Duration> {
         let timer_response: TimerResponse = serde_json::from_str(response)?;
         let mut earliest_available = None;
@@ -201,12 +303,12 @@ impl PlaceClient {
             }
         }
 
-        // If it goes wrong try the old method and wait 31 minutes
-        Ok(Duration::from_secs(BATCH_DELAY_MINUTES * 60))
+        // If it goes wrong try the old method and wait the whole batch delay
+        Ok(self.batch_delay)
     }
 
-    async fn place_pixel(&self, auth: &mut Auth, x: i32, y: i32, color_id: u8) -> Result<(bool, Option<Duration>)> {
-        let url = format!("{}/api/set", self.base_url);
+    async fn place_pixel(&self, auth: &mut Auth, x: i32, y: i32, color_id: u8) -> Result<PlaceOutcome> {
+        let url = format!("{}{}", self.base_url, self.set_endpoint);
         
         let request = PlacePixelRequest {
             x,
@@ -245,16 +347,16 @@ impl PlaceClient {
                 let cookie_str = cookie.to_str()?;
                 
                 if let Some(token_str) = cookie_str.split(';').next() {
-                    if token_str.starts_with("token=") {
-                        auth.token = token_str["token=".len()..].to_string();
-                    } else if token_str.starts_with("refresh=") {
-                        auth.refresh_token = token_str["refresh=".len()..].to_string();
+                    if let Some(token) = token_str.strip_prefix("token=") {
+                        auth.token = token.to_string();
+                    } else if let Some(refresh) = token_str.strip_prefix("refresh=") {
+                        auth.refresh_token = refresh.to_string();
                     }
                 }
             }
 
             debug!("New tokens: refresh={}, token={}", auth.refresh_token, auth.token);
-            return Ok((true, None));
+            return Ok(PlaceOutcome::RefreshTokens);
         } 
 
         if !status.is_success() {
@@ -264,7 +366,7 @@ impl PlaceClient {
                 if timer_response.message.as_deref() == Some("Too early") {
                     let wait_duration = self.calculate_wait_interval(&response_text)?;
                     info!("Waiting for {:?} before retrying", wait_duration);
-                    return Ok((false, Some(wait_duration)));
+                    return Ok(PlaceOutcome::TooEarly(wait_duration));
                 }
             }
             return Err(anyhow!("Request failed with status: {} - {}", status, response_text));
@@ -281,77 +383,88 @@ impl PlaceClient {
         }
 
         info!("Successfully placed pixel at ({}, {}) with color id {}", x, y, color_id);
-        Ok((false, wait_duration))
+        Ok(PlaceOutcome::Placed(wait_duration))
     }
 
-    pub(crate) async fn process_pattern(&self,
-                                        auth: &mut Auth,
-                                        pattern: &Pattern,
-                                        start_x: i32,
-                                        start_y: i32,
-                                        board: &Vec<Vec<u8>>,
-                                        max_pixels: usize
-    ) -> Result<(usize, Option<Duration>)> {
+    /// Place les pixels `(x, y, color)` dans l'ordre, renvoie le nombre de pixels posés, le nombre
+    /// de pixels reportés et le temps d'attente le plus court annoncé par le serveur.
+    /// Au premier "Too early", le reste du batch est reporté sans autre requête.
+    pub(crate) async fn place_batch(&self, auth: &mut Auth, pixels: &[(i32, i32, u8)]) -> Result<(usize, usize, Option<Duration>)> {
         let mut pixels_placed = 0;
         let mut wait_duration = None;
 
-        for p in &pattern.pattern {
-            if pixels_placed >= max_pixels {
-                break;
-            }
+        for (index, &(target_x, target_y, color)) in pixels.iter().enumerate() {
+            let mut retries = 0;
+            let max_retries = 3;
 
-            let target_x: i32 = start_x + p.x;
-            let target_y: i32 = start_y + p.y;
-            
-            if target_x >= BOARD_SIZE as i32 || target_y >= BOARD_SIZE as i32 {
-                error!("Pattern point ({}, {}) out of bounds", target_x, target_y);
-                continue;
-            }
+            while retries < max_retries {
+                match self.place_pixel(auth, target_x, target_y, color).await {
+                    Ok(PlaceOutcome::RefreshTokens) => {
+                        info!("Retrying with new tokens");
+                        continue;
+                    },
+                    Ok(PlaceOutcome::TooEarly(duration)) => {
+                        // Le serveur refusera aussi les suivants : ils restent faux et seront replanifiés
+                        let pixels_deferred = pixels.len() - index;
+                        info!("Too early at ({}, {}), {} pixels deferred to the next batch", target_x, target_y, pixels_deferred);
+                        let wait_duration = wait_duration.map_or(duration, |current: Duration| current.min(duration));
+                        return Ok((pixels_placed, pixels_deferred, Some(wait_duration)));
+                    },
+                    Ok(PlaceOutcome::Placed(new_wait_duration)) => {
+                        // Mise à jour du temps d'attente si besoin
+                        if let Some(duration) = new_wait_duration {
+                            wait_duration = Some(wait_duration.map_or(duration, |current: Duration| current.min(duration)));
+                        }
 
-            if board[target_y as usize][target_x as usize] != p.color {
-                let mut retries = 0;
-                let max_retries = 3;
-
-                while retries < max_retries {
-                    match self.place_pixel(auth, target_x, target_y, p.color).await {
-                        Ok((needs_refresh, new_wait_duration)) => {
-                            if needs_refresh {
-                                info!("Retrying with new tokens");
-                                continue;
-                            }
-                            
-                            // Mise à jour du temps d'attente si besoin
-                            if let Some(duration) = new_wait_duration {
-                                match wait_duration {
-                                    None => wait_duration = Some(duration),
-                                    Some(current) => {
-                                        if duration < current {
-                                            wait_duration = Some(duration);
-                                        }
-                                    }
-                                }
-                            }
-                            
-                            info!("Successfully placed pixel at ({}, {})", target_x, target_y);
-                            pixels_placed += 1;
+                        info!("Successfully placed pixel at ({}, {})", target_x, target_y);
+                        pixels_placed += 1;
+                        break;
+                    },
+                    Err(e) => {
+                        error!("Failed to place pixel: {}", e);
+                        retries += 1;
+                        if retries >= max_retries {
+                            error!("Max retries reached for pixel ({}, {}), skipping", target_x, target_y);
                             break;
-                        },
-                        Err(e) => {
-                            error!("Failed to place pixel: {}", e);
-                            retries += 1;
-                            if retries >= max_retries {
-                                error!("Max retries reached for pixel ({}, {}), skipping", target_x, target_y);
-                                break;
-                            }
                         }
                     }
-                    sleep(Duration::from_millis(500)).await;
                 }
-            } else {
-                debug!("Pixel at ({}, {}) already has correct color {}", target_x, target_y, p.color);
+                sleep(Duration::from_millis(500)).await;
             }
         }
 
-        Ok((pixels_placed, wait_duration))
+        Ok((pixels_placed, 0, wait_duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(color_id: u8) -> Pixel {
+        Pixel { username: String::new(), color_id, set_time: String::new() }
+    }
+
+    #[test]
+    fn orientation_check_uses_the_fetched_board() {
+        // Matrice du serveur de 2 lignes et 3 colonnes : board[x][y], le pixel (1, 2) a la couleur 6
+        let matrix: Vec<Vec<Pixel>> = vec![
+            vec![pixel(1), pixel(2), pixel(3)],
+            vec![pixel(4), pixel(5), pixel(6)],
+        ];
+        let check = |orientation: Orientation, x: i32, y: i32, color: u8| {
+            let client = PlaceClient::builder().orientation(orientation).build();
+            let board = PlaceClient::build_board(&matrix, orientation).unwrap();
+            client.check_orientation(&board, x, y, color)
+        };
+
+        assert!(check(Orientation::Transpose, 1, 2, 6).is_ok());
+        // Lue ligne par ligne, la board fait 3x2 : (1, 2) n'existe pas et (2, 1) vaut 6
+        let error = check(Orientation::None, 1, 2, 6).unwrap_err().to_string();
+        assert!(error.contains("has None with orientation none"), "{}", error);
+        assert!(error.contains("matching this pixel: [transpose]"), "{}", error);
+        assert!(check(Orientation::None, 2, 1, 6).is_ok());
+        let error = check(Orientation::Transpose, 2, 1, 6).unwrap_err().to_string();
+        assert!(error.contains("matching this pixel: [none]"), "{}", error);
     }
 }
@@ -0,0 +1,453 @@
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use crate::args_parser::ArgSpecs;
+use crate::board::Board;
+use crate::palette::perceptual_distance;
+use crate::pattern::{Pattern, PixelColor};
+use crate::place_client::Color;
+
+/// Ordre dans lequel les pixels faux d'un pattern sont placés (`order` dans `[pattern.options]`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PixelOrder {
+    /// Le pixel faux depuis le plus longtemps d'abord, puis l'ordre du fichier
+    #[default]
+    OldestFirst,
+    /// L'ordre du fichier
+    File,
+    /// Ligne par ligne, de haut en bas puis de gauche à droite
+    Scanline,
+    /// Le contour du dessin d'abord, puis vers l'intérieur
+    OutlineFirst,
+    /// Du centre du pattern vers les bords
+    CenterOut,
+    /// Aléatoire, mais identique d'un cycle à l'autre pour une même `seed`
+    Random,
+    /// Le pixel abîmé le plus récemment d'abord
+    RecentlyDamaged,
+    /// La plus grande différence de couleur perçue (CIEDE2000) entre la board et le pattern d'abord
+    ColorError,
+}
+
+/// Ce qu'une case de la board doit devenir, après résolution des priorités entre patterns
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetCell {
+    /// Index du pattern gagnant dans la liste triée par priorité
+    pub pattern: usize,
+    /// Position du pixel dans le fichier du pattern
+    pub order: usize,
+    pub color: PixelColor,
+    /// Poids du pixel dans son pattern
+    pub weight: u32,
+}
+
+/// Cible de chaque case couverte par au moins un pattern, en coordonnées de la board.
+///
+/// Les patterns sont donnés du plus prioritaire au moins prioritaire : sur une case disputée,
+/// le premier qui la réclame la garde.
+pub fn build_target_map(patterns: &[(&ArgSpecs, &Pattern)], board: &Board) -> HashMap<(i32, i32), TargetCell> {
+    let mut targets = HashMap::new();
+    for (index, (spec, pattern)) in patterns.iter().enumerate() {
+        let mut off_board = 0;
+        for (order, p) in pattern.pixels().enumerate() {
+            let (x, y) = (spec.x + p.x, spec.y + p.y);
+            // Coordonnées négatives comprises : la board ne contient que les cases valides
+            if !board.contains(x, y) {
+                off_board += 1;
+                continue;
+            }
+            targets.entry((x, y)).or_insert(TargetCell { pattern: index, order, color: p.color.clone(), weight: p.weight() });
+        }
+        // Déjà signalé en warning par la validation du démarrage, la carte est reconstruite à chaque cycle
+        if off_board > 0 {
+            debug!("{} pixels of pattern {} at ({}, {}) fall outside of the {}x{} board and were skipped",
+                off_board, spec.name, spec.x, spec.y, board.width(), board.height());
+        }
+    }
+    targets
+}
+
+/// Un pixel à corriger, avec tout ce qui sert à le classer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanEntry {
+    pub x: i32,
+    pub y: i32,
+    /// Couleur à poser
+    pub color: u8,
+    /// Couleur actuelle sur la board
+    pub current: u8,
+    pub pattern: usize,
+    pub priority: u32,
+    pub weight: u32,
+    /// Poids du pixel dans son pattern, passe avant le `PixelOrder`
+    pub pixel_weight: u32,
+    pub order: usize,
+    /// Rang du pixel parmi les pixels faux de son pattern, selon son `PixelOrder`
+    pub rank: usize,
+    /// Date de la mauvaise couleur actuelle, `None` si le serveur ne la donne pas
+    pub set_time: Option<DateTime<Utc>>,
+    /// Date la plus ancienne parmi ce pixel et ceux classés après lui dans son pattern : le pattern
+    /// est aussi urgent que son plus vieux pixel faux, sans changer l'ordre de ses pixels
+    pub stale_since: Option<DateTime<Utc>>,
+}
+
+/// Tous les pixels faux de la board, classés du plus urgent au moins urgent
+#[derive(Debug, Clone)]
+pub struct PlacementPlan {
+    entries: Vec<PlanEntry>,
+    names: Vec<String>,
+    /// Limite de pixels par batch de chaque pattern
+    caps: Vec<Option<usize>>,
+}
+
+impl PlacementPlan {
+    /// Classement : priorité du pattern, puis poids du pattern, puis poids du pixel, puis le pixel faux depuis le plus longtemps
+    /// tous patterns confondus (`stale_since`, date inconnue d'abord comme `PixelOrder::OldestFirst`),
+    /// puis rang dans le pattern (voir `PixelOrder`), puis ordre des patterns.
+    pub fn build(
+        patterns: &[(&ArgSpecs, &Pattern)],
+        board: &Board,
+        colors: &HashMap<u8, Color>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut by_pattern: Vec<Vec<PlanEntry>> = vec![Vec::new(); patterns.len()];
+        for ((x, y), target) in build_target_map(patterns, board) {
+            let current = board.get(x, y).expect("target cells are on the board");
+            if target.color.accepts(current) {
+                continue;
+            }
+            // "any" accepte toute couleur, preferred existe donc toujours ici
+            let Some(color) = target.color.preferred() else {
+                continue;
+            };
+
+            let spec = patterns[target.pattern].0;
+            let info = board.info(x, y).cloned().unwrap_or_default();
+            let age = board.age(x, y, now)
+                .map_or("unknown age".to_string(), |age| format!("{}m ago", age.num_minutes()));
+            debug!("Pixel at ({}, {}) has color {} instead of {}, set by {} ({})",
+                x, y, current, target.color, info.username_or_unknown(), age);
+
+            by_pattern[target.pattern].push(PlanEntry {
+                x,
+                y,
+                color,
+                current,
+                pattern: target.pattern,
+                priority: spec.priority,
+                weight: spec.options.weight.unwrap_or(1),
+                pixel_weight: target.weight,
+                order: target.order,
+                rank: 0,
+                set_time: info.set_time,
+                stale_since: info.set_time,
+            });
+        }
+
+        for (group, (spec, pattern)) in by_pattern.iter_mut().zip(patterns) {
+            sort_pattern_entries(group, spec, pattern, colors);
+            for (rank, entry) in group.iter_mut().enumerate() {
+                entry.rank = rank;
+            }
+            // Avec `oldest-first`, c'est la date du pixel lui-même
+            let mut oldest: Option<Option<DateTime<Utc>>> = None;
+            for entry in group.iter_mut().rev() {
+                let date = oldest.map_or(entry.set_time, |date| date.min(entry.set_time));
+                entry.stale_since = date;
+                oldest = Some(date);
+            }
+        }
+        let mut entries: Vec<PlanEntry> = by_pattern.into_iter().flatten().collect();
+        entries.sort_by(|a, b| {
+            a.priority.cmp(&b.priority)
+                .then(b.weight.cmp(&a.weight))
+                .then(b.pixel_weight.cmp(&a.pixel_weight))
+                .then(a.stale_since.cmp(&b.stale_since))
+                .then(a.rank.cmp(&b.rank))
+                .then(a.pattern.cmp(&b.pattern))
+        });
+
+        PlacementPlan {
+            entries,
+            names: patterns.iter().map(|(spec, _)| spec.name.clone()).collect(),
+            caps: patterns.iter().map(|(spec, _)| spec.options.max_pixels_per_batch).collect(),
+        }
+    }
+
+    /// Pixels faux, du plus urgent au moins urgent
+    #[cfg(test)]
+    pub fn entries(&self) -> &[PlanEntry] {
+        &self.entries
+    }
+
+    pub fn pattern_name(&self, entry: &PlanEntry) -> &str {
+        &self.names[entry.pattern]
+    }
+
+    /// Prend dans l'ordre les pixels qui rentrent dans un batch, renvoie `(batch, reste)`
+    fn take_batch<'a>(&self, remaining: Vec<&'a PlanEntry>, max_pixels: usize) -> (Vec<&'a PlanEntry>, Vec<&'a PlanEntry>) {
+        let mut counts = vec![0; self.caps.len()];
+        let mut batch = Vec::new();
+        let mut rest = Vec::new();
+        for entry in remaining {
+            let cap = self.caps[entry.pattern].unwrap_or(usize::MAX);
+            if batch.len() < max_pixels && counts[entry.pattern] < cap {
+                counts[entry.pattern] += 1;
+                batch.push(entry);
+            } else {
+                rest.push(entry);
+            }
+        }
+        (batch, rest)
+    }
+
+    /// Pixels du prochain batch, en respectant la limite globale et celle de chaque pattern
+    pub fn next_batch(&self, max_pixels: usize) -> Vec<&PlanEntry> {
+        self.take_batch(self.entries.iter().collect(), max_pixels).0
+    }
+
+    /// Découpage complet du plan en batches successifs, si la board ne changeait plus
+    pub fn batches(&self, max_pixels: usize) -> Vec<Vec<&PlanEntry>> {
+        let mut remaining: Vec<&PlanEntry> = self.entries.iter().collect();
+        let mut batches = Vec::new();
+        while !remaining.is_empty() && max_pixels > 0 {
+            let (batch, rest) = self.take_batch(remaining, max_pixels);
+            batches.push(batch);
+            remaining = rest;
+        }
+        batches
+    }
+
+    /// Nombre de pixels faux par pattern, puis le prochain batch en debug
+    pub fn log_summary(&self, max_pixels: usize) {
+        let mut counts = vec![0; self.names.len()];
+        for entry in &self.entries {
+            counts[entry.pattern] += 1;
+        }
+        info!("Placement plan: {} wrong pixels", self.entries.len());
+        for (name, count) in self.names.iter().zip(counts) {
+            info!("  {}: {} wrong pixels", name, count);
+        }
+        for entry in self.next_batch(max_pixels) {
+            debug!("  next: ({}, {}) {} -> {} for {} (priority {}, weight {})",
+                entry.x, entry.y, entry.current, entry.color, self.pattern_name(entry), entry.priority, entry.weight);
+        }
+    }
+}
+
+/// Trie les pixels faux d'un seul pattern : les pixels les plus lourds d'abord, puis selon
+/// son `PixelOrder`, l'ordre du fichier départageant le reste
+fn sort_pattern_entries(entries: &mut [PlanEntry], spec: &ArgSpecs, pattern: &Pattern, colors: &HashMap<u8, Color>) {
+    let local = |e: &PlanEntry| (e.x - spec.x, e.y - spec.y);
+    match spec.options.order.unwrap_or_default() {
+        PixelOrder::OldestFirst => entries.sort_by_key(|e| (e.set_time, e.order)),
+        PixelOrder::File => entries.sort_by_key(|e| e.order),
+        PixelOrder::Scanline => entries.sort_by_key(|e| {
+            let (x, y) = local(e);
+            (y, x)
+        }),
+        PixelOrder::OutlineFirst => {
+            let depths = outline_depths(pattern);
+            entries.sort_by_key(|e| (depths.get(&local(e)).copied().unwrap_or(0), e.order));
+        }
+        PixelOrder::CenterOut => {
+            // Distances doublées pour rester en entiers avec un centre entre deux cases
+            let (width, height) = pattern.size();
+            entries.sort_by_key(|e| {
+                let (x, y) = local(e);
+                let (dx, dy) = ((2 * x + 1 - width as i32) as i64, (2 * y + 1 - height as i32) as i64);
+                (dx * dx + dy * dy, e.order)
+            });
+        }
+        PixelOrder::Random => {
+            let seed = spec.options.seed.unwrap_or(0);
+            entries.sort_by_key(|e| {
+                let (x, y) = local(e);
+                (shuffle_key(seed, x, y), e.order)
+            });
+        }
+        // None < Some : en ordre décroissant les dates inconnues passent en dernier
+        PixelOrder::RecentlyDamaged => entries.sort_by(|a, b| b.set_time.cmp(&a.set_time).then(a.order.cmp(&b.order))),
+        PixelOrder::ColorError => {
+            let error = |e: &PlanEntry| match (colors.get(&e.current), colors.get(&e.color)) {
+                (Some(current), Some(target)) => perceptual_distance(current, target),
+                _ => 0.0,
+            };
+            entries.sort_by(|a, b| error(b).total_cmp(&error(a)).then(a.order.cmp(&b.order)));
+        }
+    }
+    // Tri stable : à poids égal, l'ordre ci-dessus est conservé
+    entries.sort_by_key(|e| Reverse(e.pixel_weight));
+}
+
+/// Distance de chaque case du dessin à son contour, en coordonnées du pattern.
+///
+/// Le contour (distance 0) regroupe les cases dont un voisin direct n'est pas dessiné ;
+/// les cases "any" ne font pas partie du dessin.
+fn outline_depths(pattern: &Pattern) -> HashMap<(i32, i32), u32> {
+    let cells: HashSet<(i32, i32)> = pattern
+        .pixels()
+        .filter(|p| p.color != PixelColor::Any)
+        .map(|p| (p.x, p.y))
+        .collect();
+    let neighbours = |(x, y): (i32, i32)| [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)];
+
+    let mut depths = HashMap::new();
+    let mut queue = VecDeque::new();
+    for &cell in &cells {
+        if neighbours(cell).iter().any(|n| !cells.contains(n)) {
+            depths.insert(cell, 0);
+            queue.push_back(cell);
+        }
+    }
+    while let Some(cell) = queue.pop_front() {
+        let depth = depths[&cell] + 1;
+        for n in neighbours(cell) {
+            if cells.contains(&n) && !depths.contains_key(&n) {
+                depths.insert(n, depth);
+                queue.push_back(n);
+            }
+        }
+    }
+    depths
+}
+
+/// Mélange déterministe (splitmix64) de la seed et de la position du pixel
+fn shuffle_key(seed: u64, x: i32, y: i32) -> u64 {
+    let mut z = seed ^ (((x as u32 as u64) << 32) | y as u32 as u64);
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args_parser::PatternOptions;
+    use crate::board::{Orientation, PixelInfo};
+    use crate::pattern::PatternPixel;
+    use crate::test_utils::{exact_row, spec};
+
+    // Board 6x2 de couleur 1, la case (x, 0) a été posée à la minute 10 - x
+    fn board() -> Board {
+        Board::from_matrix_with_info(2, 6, Orientation::None, |i, j| {
+            let minute = 10 - j as u32;
+            (1, PixelInfo::new("someone", &format!("2024-01-01T00:{:02}:00Z", minute * (1 - i as u32))))
+        })
+    }
+
+    #[test]
+    fn overlaps_go_to_the_higher_priority_pattern() {
+        let (high, low) = (exact_row(&[2, 2]), exact_row(&[3, 3, 3]));
+        let specs = [spec("high", 1, 0, 1, PatternOptions::default()), spec("low", 0, 0, 2, PatternOptions::default())];
+        let patterns = [(&specs[0], &high), (&specs[1], &low)];
+
+        let targets = build_target_map(&patterns, &board());
+        assert_eq!(targets.len(), 3);
+        assert_eq!(targets[&(0, 0)].pattern, 1);
+        assert_eq!(targets[&(1, 0)], TargetCell { pattern: 0, order: 0, color: PixelColor::Exact(2), weight: 1 });
+        assert_eq!(targets[&(2, 0)].pattern, 0);
+    }
+
+    #[test]
+    fn plan_ranks_by_priority_weight_then_staleness() {
+        let (a, b, c) = (exact_row(&[2, 2]), exact_row(&[3, 3]), exact_row(&[4, 1]));
+        let heavy = PatternOptions { weight: Some(5), ..PatternOptions::default() };
+        let specs = [
+            spec("a", 0, 0, 2, PatternOptions::default()),
+            spec("b", 2, 0, 2, heavy),
+            spec("c", 4, 0, 1, PatternOptions { max_pixels_per_batch: Some(1), ..PatternOptions::default() }),
+        ];
+        let patterns = [(&specs[0], &a), (&specs[1], &b), (&specs[2], &c)];
+        let plan = PlacementPlan::build(&patterns, &board(), &HashMap::new(), Utc::now());
+
+        // c est prioritaire (son 2e pixel est déjà bon), puis b plus lourd que a ;
+        // dans un même pattern, la case posée le plus tôt (x le plus grand) passe d'abord
+        let order: Vec<(i32, i32)> = plan.entries().iter().map(|e| (e.x, e.y)).collect();
+        assert_eq!(order, vec![(4, 0), (3, 0), (2, 0), (1, 0), (0, 0)]);
+
+        let batches: Vec<Vec<i32>> = plan.batches(2).iter().map(|batch| batch.iter().map(|e| e.x).collect()).collect();
+        assert_eq!(batches, vec![vec![4, 3], vec![2, 1], vec![0]]);
+    }
+
+    #[test]
+    fn stale_pixels_win_across_patterns() {
+        // Même priorité et même poids : le pixel de b posé à la minute 6 passe avant
+        // ceux de a (minutes 9 et 10), bien que a soit déclaré en premier
+        let (a, b) = (exact_row(&[2, 2]), exact_row(&[3]));
+        let specs = [spec("a", 0, 0, 1, PatternOptions::default()), spec("b", 4, 0, 1, PatternOptions::default())];
+        let plan = PlacementPlan::build(&[(&specs[0], &a), (&specs[1], &b)], &board(), &HashMap::new(), Utc::now());
+        let order: Vec<(i32, usize)> = plan.entries().iter().map(|e| (e.x, e.pattern)).collect();
+        assert_eq!(order, vec![(4, 1), (1, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn heavy_pixels_win_across_patterns() {
+        // Le pixel lourd de b, posé à la minute 10, passe avant le pixel le plus ancien de a
+        let a = exact_row(&[2]);
+        let b = Pattern::new(1, 1, vec![PatternPixel { x: 0, y: 0, color: PixelColor::Exact(3), weight: Some(4) }]);
+        let specs = [spec("a", 5, 0, 1, PatternOptions::default()), spec("b", 0, 0, 1, PatternOptions::default())];
+        let plan = PlacementPlan::build(&[(&specs[0], &a), (&specs[1], &b)], &board(), &HashMap::new(), Utc::now());
+        let order: Vec<(i32, usize)> = plan.entries().iter().map(|e| (e.x, e.pattern)).collect();
+        assert_eq!(order, vec![(0, 1), (5, 0)]);
+    }
+
+    #[test]
+    fn pixel_orders_within_a_pattern() {
+        // Carré 3x3 tout faux sur une board de couleur 1, la case (x, y) posée à la minute 3 * y + x
+        let board = Board::from_matrix_with_info(3, 3, Orientation::None, |i, j| {
+            (1, PixelInfo::new("someone", &format!("2024-01-01T00:{:02}:00Z", 3 * i + j)))
+        });
+        // Fichier écrit colonne par colonne, pour distinguer `file` de `scanline`
+        let pixels = (0..9).map(|i| PatternPixel { x: i / 3, y: i % 3, color: PixelColor::Exact(2), weight: None }).collect();
+        let square = Pattern::new(3, 3, pixels);
+        let ranked = |order: PixelOrder| -> Vec<(i32, i32)> {
+            let spec = spec("square", 0, 0, 1, PatternOptions { order: Some(order), ..PatternOptions::default() });
+            let plan = PlacementPlan::build(&[(&spec, &square)], &board, &HashMap::new(), Utc::now());
+            plan.entries().iter().map(|e| (e.x, e.y)).collect()
+        };
+
+        assert_eq!(ranked(PixelOrder::OutlineFirst).last(), Some(&(1, 1)));
+        assert_eq!(ranked(PixelOrder::CenterOut)[0], (1, 1));
+        assert_eq!(ranked(PixelOrder::RecentlyDamaged)[..2], [(2, 2), (1, 2)]);
+        assert_eq!(ranked(PixelOrder::OldestFirst)[..2], [(0, 0), (1, 0)]);
+        assert_eq!(ranked(PixelOrder::File)[..4], [(0, 0), (0, 1), (0, 2), (1, 0)]);
+        assert_eq!(ranked(PixelOrder::Scanline)[..4], [(0, 0), (1, 0), (2, 0), (0, 1)]);
+
+        // Colonne de droite plus lourde : elle passe avant le scanline
+        let weighted = Pattern::new(3, 3, square.pixels().map(|p| PatternPixel { weight: (p.x == 2).then_some(3), ..p.clone() }).collect());
+        let spec = spec("weighted", 0, 0, 1, PatternOptions { order: Some(PixelOrder::Scanline), ..PatternOptions::default() });
+        let plan = PlacementPlan::build(&[(&spec, &weighted)], &board, &HashMap::new(), Utc::now());
+        let first: Vec<(i32, i32)> = plan.entries().iter().take(4).map(|e| (e.x, e.y)).collect();
+        assert_eq!(first, vec![(2, 0), (2, 1), (2, 2), (0, 0)]);
+
+        let random = ranked(PixelOrder::Random);
+        assert_eq!(random, ranked(PixelOrder::Random));
+        assert_ne!(random, ranked(PixelOrder::Scanline));
+    }
+
+    #[test]
+    fn color_error_places_the_farthest_color_first() {
+        // Ligne blanche (1) à poser sur du gris clair (2), du noir (4) et du gris foncé (3)
+        let board = Board::from_matrix(1, 3, Orientation::None, |_, j| [2, 4, 3][j]);
+        let palette = crate::palette::Palette::bundled();
+        let colors: HashMap<u8, Color> = palette.ids().map(|id| (id, palette.get(id).unwrap().clone())).collect();
+        let spec = spec("white", 0, 0, 1, PatternOptions { order: Some(PixelOrder::ColorError), ..PatternOptions::default() });
+        let line = exact_row(&[1, 1, 1]);
+
+        let ranked = |colors: &HashMap<u8, Color>| -> Vec<i32> {
+            let plan = PlacementPlan::build(&[(&spec, &line)], &board, colors, Utc::now());
+            plan.entries().iter().map(|e| e.x).collect()
+        };
+        assert_eq!(ranked(&colors), vec![1, 2, 0]);
+        // Sans les RGB du serveur, toutes les erreurs valent 0 : l'ordre du fichier
+        assert_eq!(ranked(&HashMap::new()), vec![0, 1, 2]);
+    }
+}
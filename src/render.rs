@@ -0,0 +1,173 @@
+use anyhow::{Result, anyhow};
+use image::{imageops, Rgb, RgbImage};
+use log::{info, warn};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::args_parser::RenderPatternArgs;
+use crate::ascii::GridEncoding;
+use crate::palette::Palette;
+use crate::pattern::Pattern;
+
+// Damier affiché sous les cases vides du pattern
+const CHECKER_LIGHT: Rgb<u8> = Rgb([255, 255, 255]);
+const CHECKER_DARK: Rgb<u8> = Rgb([220, 220, 220]);
+const ANY_MARK: Rgb<u8> = Rgb([150, 150, 150]);
+const GRID_COLOR: Rgb<u8> = Rgb([90, 90, 90]);
+const SHEET_BACKGROUND: Rgb<u8> = Rgb([40, 40, 40]);
+const SHEET_PADDING: u32 = 16;
+// Taille maximale d'une image générée (64 Mpx, environ 200 Mo en RGB)
+const MAX_IMAGE_PIXELS: u64 = 1 << 26;
+
+/// `(width, height)` si les deux tailles ont été calculées sans débordement et restent sous `MAX_IMAGE_PIXELS`
+fn image_size(width: Option<u32>, height: Option<u32>) -> Option<(u32, u32)> {
+    let (width, height) = (width?, height?);
+    (width as u64 * height as u64 <= MAX_IMAGE_PIXELS).then_some((width, height))
+}
+
+/// Dessine le pattern, chaque pixel devient un carré de `zoom x zoom`.
+/// Échoue si l'image dépasse `MAX_IMAGE_PIXELS`, la taille déclarée d'un pattern n'étant pas bornée.
+pub fn render_pattern(pattern: &Pattern, palette: &Palette, zoom: u32, grid: bool) -> Result<RgbImage> {
+    let (width, height) = pattern.size();
+    let (image_width, image_height) = image_size(width.max(1).checked_mul(zoom), height.max(1).checked_mul(zoom))
+        .ok_or_else(|| anyhow!("Preview of the {}x{} pattern at zoom {} is too large (more than {} pixels)",
+            width, height, zoom, MAX_IMAGE_PIXELS))?;
+    let mut img = RgbImage::from_fn(image_width, image_height, |x, y| {
+        // Une case de damier par pixel du pattern
+        if ((x / zoom) + (y / zoom)).is_multiple_of(2) { CHECKER_LIGHT } else { CHECKER_DARK }
+    });
+
+    // Les pixels du pattern sont toujours dans sa boîte
+    for p in pattern.pixels() {
+        let rgb = match p.color.preferred() {
+            Some(id) => match palette.get(id) {
+                Some(color) => Rgb([color.red, color.green, color.blue]),
+                None => {
+                    warn!("Unknown color id {} at ({}, {})", id, p.x, p.y);
+                    Rgb([0, 0, 0])
+                }
+            },
+            None => {
+                // "any" : une croix sur le damier, le pixel fait partie du pattern sans couleur imposée
+                for d in 0..zoom {
+                    img.put_pixel(p.x as u32 * zoom + d, p.y as u32 * zoom + d, ANY_MARK);
+                    img.put_pixel(p.x as u32 * zoom + zoom - 1 - d, p.y as u32 * zoom + d, ANY_MARK);
+                }
+                continue;
+            }
+        };
+        for dy in 0..zoom {
+            for dx in 0..zoom {
+                img.put_pixel(p.x as u32 * zoom + dx, p.y as u32 * zoom + dy, rgb);
+            }
+        }
+    }
+
+    // La grille n'est lisible qu'à partir d'un zoom de 3
+    if grid && zoom >= 3 {
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            if x.is_multiple_of(zoom) || y.is_multiple_of(zoom) {
+                *pixel = GRID_COLOR;
+            }
+        }
+    }
+    Ok(img)
+}
+
+/// Fichiers pattern `.json`, `.txt` et `.rle` du dossier, triés par nom
+fn pattern_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| anyhow!("Couldn't read directory {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json") || GridEncoding::from_path(path).is_some())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Planche contact : tous les patterns du dossier, rangés en grille, avec la légende "ligne, colonne: fichier"
+fn render_contact_sheet(dir: &Path, palette: &Palette, zoom: u32, grid: bool) -> Result<(RgbImage, Vec<String>)> {
+    let mut tiles = Vec::new();
+    for path in pattern_files(dir)? {
+        match Pattern::load(&path.to_string_lossy()).and_then(|pattern| render_pattern(&pattern, palette, zoom, grid)) {
+            Ok(tile) => tiles.push((path, tile)),
+            Err(e) => warn!("Skipping {}: {}", path.display(), e),
+        }
+    }
+    if tiles.is_empty() {
+        return Err(anyhow!("No pattern found in {}", dir.display()));
+    }
+
+    let columns = (tiles.len() as f64).sqrt().ceil() as u32;
+    let rows = (tiles.len() as u32).div_ceil(columns);
+    let cell_width = tiles.iter().map(|(_, tile)| tile.width()).max().unwrap_or(0) + SHEET_PADDING;
+    let cell_height = tiles.iter().map(|(_, tile)| tile.height()).max().unwrap_or(0) + SHEET_PADDING;
+
+    let (sheet_width, sheet_height) = image_size(
+        columns.checked_mul(cell_width).and_then(|width| width.checked_add(SHEET_PADDING)),
+        rows.checked_mul(cell_height).and_then(|height| height.checked_add(SHEET_PADDING)),
+    )
+    .ok_or_else(|| anyhow!("Contact sheet of {} patterns is too large (more than {} pixels)", tiles.len(), MAX_IMAGE_PIXELS))?;
+    let mut sheet = RgbImage::from_pixel(sheet_width, sheet_height, SHEET_BACKGROUND);
+    let mut legend = Vec::new();
+    for (i, (path, tile)) in tiles.iter().enumerate() {
+        let column = i as u32 % columns;
+        let row = i as u32 / columns;
+        legend.push(format!("row {} column {}: {}", row + 1, column + 1, path.display()));
+        imageops::replace(
+            &mut sheet,
+            tile,
+            (SHEET_PADDING + column * cell_width) as i64,
+            (SHEET_PADDING + row * cell_height) as i64,
+        );
+    }
+    Ok((sheet, legend))
+}
+
+pub fn run(args: &RenderPatternArgs, snapshot_dir: &Path) -> Result<()> {
+    if args.zoom == 0 {
+        return Err(anyhow!("--zoom must be at least 1"));
+    }
+
+    let palette = Palette::resolve(args.palette.as_deref(), snapshot_dir)?;
+
+    let img = match (&args.contact_sheet, &args.input) {
+        (Some(dir), _) => {
+            let (sheet, legend) = render_contact_sheet(dir, &palette, args.zoom, args.grid)?;
+            for line in legend {
+                println!("{}", line);
+            }
+            sheet
+        }
+        (None, Some(input)) => {
+            let pattern = Pattern::load(&input.to_string_lossy())?;
+            render_pattern(&pattern, &palette, args.zoom, args.grid)?
+        }
+        (None, None) => return Err(anyhow!("Give a pattern file or --contact-sheet <dir>")),
+    };
+
+    img.save(&args.output)?;
+    info!("Preview {}x{} written to {}", img.width(), img.height(), args.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::exact_row;
+
+    #[test]
+    fn oversized_previews_are_refused() {
+        let palette = Palette::bundled();
+        let img = render_pattern(&exact_row(&[1, 2]), &palette, 3, false).unwrap();
+        assert_eq!(img.dimensions(), (6, 3));
+
+        let wide = Pattern::new(3_000_000_000, 1, Vec::new());
+        assert!(render_pattern(&wide, &palette, 2, false).unwrap_err().to_string().contains("too large"));
+        let large = Pattern::new(10_000, 10_000, Vec::new());
+        assert!(render_pattern(&large, &palette, 1, false).is_err());
+    }
+}
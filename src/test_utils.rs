@@ -0,0 +1,31 @@
+// Fabriques partagées par les tests unitaires
+
+use crate::args_parser::{ArgSpecs, PatternOptions};
+use crate::pattern::{Pattern, PatternPixel, PixelColor};
+
+pub fn spec(name: &str, x: i32, y: i32, priority: u32, options: PatternOptions) -> ArgSpecs {
+    ArgSpecs {
+        name: name.to_string(),
+        pattern_path: String::new(),
+        x,
+        y,
+        priority,
+        options,
+        auto_origin: false,
+    }
+}
+
+/// Pattern d'une seule ligne, un pixel par couleur à partir de (0, 0)
+pub fn row(colors: &[PixelColor]) -> Pattern {
+    let pixels = colors
+        .iter()
+        .enumerate()
+        .map(|(x, color)| PatternPixel { x: x as i32, y: 0, color: color.clone(), weight: None })
+        .collect();
+    Pattern::new(colors.len() as u32, 1, pixels)
+}
+
+/// Comme `row`, avec uniquement des couleurs exactes
+pub fn exact_row(colors: &[u8]) -> Pattern {
+    row(&colors.iter().map(|&id| PixelColor::Exact(id)).collect::<Vec<_>>())
+}
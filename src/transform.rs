@@ -0,0 +1,256 @@
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use log::info;
+use std::{collections::HashMap, path::Path};
+
+use crate::args_parser::TransformArgs;
+use crate::ascii::GridEncoding;
+use crate::pattern::{Pattern, PatternPixel, PixelColor};
+
+/// Rotation dans le sens horaire
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Rotation {
+    #[value(name = "90")]
+    Rotate90,
+    #[value(name = "180")]
+    Rotate180,
+    #[value(name = "270")]
+    Rotate270,
+}
+
+/// Applique `f` à chaque pixel, avec la taille de la nouvelle boîte
+fn map_pixels<F>(pattern: &Pattern, width: u32, height: u32, f: F) -> Pattern
+where
+    F: Fn(&PatternPixel) -> PatternPixel,
+{
+    Pattern::new(width, height, pattern.pixels().map(f).collect())
+}
+
+/// Miroir gauche / droite
+pub fn flip_x(pattern: &Pattern) -> Pattern {
+    let (width, height) = pattern.size();
+    map_pixels(pattern, width, height, |p| PatternPixel { x: width as i32 - 1 - p.x, ..p.clone() })
+}
+
+/// Miroir haut / bas
+pub fn flip_y(pattern: &Pattern) -> Pattern {
+    let (width, height) = pattern.size();
+    map_pixels(pattern, width, height, |p| PatternPixel { y: height as i32 - 1 - p.y, ..p.clone() })
+}
+
+pub fn rotate(pattern: &Pattern, rotation: Rotation) -> Pattern {
+    let (width, height) = pattern.size();
+    let (w, h) = (width as i32, height as i32);
+    match rotation {
+        Rotation::Rotate90 => map_pixels(pattern, height, width, |p| PatternPixel { x: h - 1 - p.y, y: p.x, ..p.clone() }),
+        Rotation::Rotate180 => map_pixels(pattern, width, height, |p| PatternPixel { x: w - 1 - p.x, y: h - 1 - p.y, ..p.clone() }),
+        Rotation::Rotate270 => map_pixels(pattern, height, width, |p| PatternPixel { x: p.y, y: w - 1 - p.x, ..p.clone() }),
+    }
+}
+
+/// Agrandit le pattern d'un facteur entier, chaque pixel devient un carré de `factor x factor`.
+/// Échoue si la nouvelle boîte ne tient pas dans des coordonnées `i32`.
+pub fn scale(pattern: &Pattern, factor: u32) -> Result<Pattern> {
+    let (width, height) = pattern.size();
+    let too_large = || anyhow!("scaled pattern too large: {}x{} times {}", width, height, factor);
+    let fits = |size: u32| size.checked_mul(factor).filter(|&scaled| i32::try_from(scaled).is_ok());
+    let (Some(scaled_width), Some(scaled_height)) = (fits(width), fits(height)) else {
+        return Err(too_large());
+    };
+    let factor = i32::try_from(factor).map_err(|_| too_large())?;
+    let cells = factor.checked_mul(factor).ok_or_else(too_large)?;
+    // Les pixels sont dans la boîte : p.x * factor + i % factor < scaled_width tient dans un i32
+    let pixels = pattern
+        .pixels()
+        .flat_map(|p| {
+            (0..cells).map(move |i| PatternPixel {
+                x: p.x * factor + i % factor,
+                y: p.y * factor + i / factor,
+                color: p.color.clone(),
+                weight: p.weight,
+            })
+        })
+        .collect();
+    Ok(Pattern::new(scaled_width, scaled_height, pixels))
+}
+
+/// Remplace les ids de couleur selon `mapping`, les ids absents restent inchangés
+pub fn recolor(pattern: &Pattern, mapping: &HashMap<u8, u8>) -> Pattern {
+    let map = |id: &u8| *mapping.get(id).unwrap_or(id);
+    let (width, height) = pattern.size();
+    map_pixels(pattern, width, height, |p| PatternPixel {
+        color: match &p.color {
+            PixelColor::Exact(id) => PixelColor::Exact(map(id)),
+            PixelColor::OneOf(ids) => PixelColor::OneOf(ids.iter().map(map).collect()),
+            PixelColor::Any => PixelColor::Any,
+        },
+        ..p.clone()
+    })
+}
+
+/// Garde le rectangle demandé, réduit à la boîte du pattern ; `(x, y)` devient la nouvelle origine
+pub fn crop(pattern: &Pattern, x: u32, y: u32, width: u32, height: u32) -> Pattern {
+    let (pattern_width, pattern_height) = pattern.size();
+    let width = width.min(pattern_width.saturating_sub(x));
+    let height = height.min(pattern_height.saturating_sub(y));
+    let pixels = pattern
+        .pixels()
+        .map(|p| PatternPixel { x: p.x - x as i32, y: p.y - y as i32, ..p.clone() })
+        .filter(|p| p.in_box(width, height))
+        .collect();
+    Pattern::new(width, height, pixels)
+}
+
+/// Donne le poids `weight` aux pixels du contour : ceux dont un voisin direct est vide
+/// ou d'une autre couleur. Les autres pixels gardent leur poids.
+pub fn edge_weight(pattern: &Pattern, weight: u32) -> Pattern {
+    let colors: HashMap<(i32, i32), &PixelColor> = pattern.pixels().map(|p| ((p.x, p.y), &p.color)).collect();
+    let (width, height) = pattern.size();
+    map_pixels(pattern, width, height, |p| {
+        let neighbours = [(p.x - 1, p.y), (p.x + 1, p.y), (p.x, p.y - 1), (p.x, p.y + 1)];
+        let is_edge = neighbours.iter().any(|n| colors.get(n) != Some(&&p.color));
+        PatternPixel { weight: if is_edge { Some(weight) } else { p.weight }, ..p.clone() }
+    })
+}
+
+/// Refuse `--edge-weight` quand la sortie est un `.txt` ou un `.rle`, qui perdraient les poids
+pub fn check_edge_weight_output(edge_weight: Option<u32>, output: &Path) -> Result<()> {
+    if edge_weight.is_some() && GridEncoding::from_path(output).is_some() {
+        return Err(anyhow!("--edge-weight needs a .json output, {} can't store pixel weights", output.display()));
+    }
+    Ok(())
+}
+
+/// `"13:14"` vers `(13, 14)`
+pub fn parse_color_pair(value: &str) -> Result<(u8, u8), String> {
+    let (from, to) = value
+        .split_once(':')
+        .ok_or_else(|| format!("expected FROM:TO, got {:?}", value))?;
+    let parse = |id: &str| id.trim().parse::<u8>().map_err(|_| format!("invalid color id {:?}", id));
+    Ok((parse(from)?, parse(to)?))
+}
+
+/// `"x,y,width,height"` vers `[x, y, width, height]`
+pub fn parse_crop(value: &str) -> Result<[u32; 4], String> {
+    let values: Vec<u32> = value
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("expected x,y,width,height, got {:?}", value))?;
+    values
+        .try_into()
+        .map_err(|_| format!("expected x,y,width,height, got {:?}", value))
+}
+
+/// Sous-commande `transform` : crop, recolor, miroirs, rotation puis agrandissement, dans cet ordre
+pub fn run(args: &TransformArgs) -> Result<()> {
+    check_edge_weight_output(args.edge_weight, &args.output)?;
+    let mut pattern = Pattern::load(&args.input.to_string_lossy())?;
+
+    if let Some([x, y, width, height]) = args.crop {
+        pattern = crop(&pattern, x, y, width, height);
+    }
+
+    let mut mapping: HashMap<u8, u8> = args.recolor.iter().copied().collect();
+    for &(a, b) in &args.swap {
+        mapping.insert(a, b);
+        mapping.insert(b, a);
+    }
+    if !mapping.is_empty() {
+        pattern = recolor(&pattern, &mapping);
+    }
+
+    if args.flip_x {
+        pattern = flip_x(&pattern);
+    }
+    if args.flip_y {
+        pattern = flip_y(&pattern);
+    }
+    if let Some(rotation) = args.rotate {
+        pattern = rotate(&pattern, rotation);
+    }
+    if let Some(factor) = args.scale {
+        if factor == 0 {
+            return Err(anyhow!("--scale must be at least 1"));
+        }
+        pattern = scale(&pattern, factor)?;
+    }
+    if let Some(weight) = args.edge_weight {
+        pattern = edge_weight(&pattern, weight);
+    }
+
+    pattern.save(&args.output)?;
+    let (width, height) = pattern.size();
+    info!("Pattern {}x{} with {} pixels written to {}",
+        width, height, pattern.pixels().count(), args.output.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(x: i32, y: i32, color: u8) -> PatternPixel {
+        PatternPixel { x, y, color: PixelColor::Exact(color), weight: None }
+    }
+
+    // 3x2 :  1 2 3
+    //        4 5 6
+    fn sample() -> Pattern {
+        Pattern::new(3, 2, vec![pixel(0, 0, 1), pixel(1, 0, 2), pixel(2, 0, 3), pixel(0, 1, 4), pixel(1, 1, 5), pixel(2, 1, 6)])
+    }
+
+    fn grid(pattern: &Pattern) -> Vec<Vec<u8>> {
+        let (width, height) = pattern.size();
+        let mut grid = vec![vec![0; width as usize]; height as usize];
+        for p in pattern.pixels() {
+            grid[p.y as usize][p.x as usize] = p.color.preferred().unwrap();
+        }
+        grid
+    }
+
+    #[test]
+    fn rotations_and_flips() {
+        assert_eq!(grid(&rotate(&sample(), Rotation::Rotate90)), vec![vec![4, 1], vec![5, 2], vec![6, 3]]);
+        assert_eq!(grid(&rotate(&sample(), Rotation::Rotate180)), vec![vec![6, 5, 4], vec![3, 2, 1]]);
+        assert_eq!(grid(&rotate(&sample(), Rotation::Rotate270)), vec![vec![3, 6], vec![2, 5], vec![1, 4]]);
+        assert_eq!(grid(&flip_x(&sample())), vec![vec![3, 2, 1], vec![6, 5, 4]]);
+        assert_eq!(grid(&flip_y(&sample())), vec![vec![4, 5, 6], vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn scale_recolor_and_crop() {
+        let scaled = scale(&crop(&sample(), 1, 0, 5, 1), 2).unwrap();
+        assert_eq!(grid(&scaled), vec![vec![2, 2, 3, 3], vec![2, 2, 3, 3]]);
+        for factor in [70000, u32::MAX] {
+            let error = scale(&sample(), factor).unwrap_err().to_string();
+            assert!(error.starts_with("scaled pattern too large"), "{}", error);
+        }
+
+        let swapped = recolor(&sample(), &HashMap::from([(1, 6), (6, 1)]));
+        assert_eq!(grid(&swapped), vec![vec![6, 2, 3], vec![4, 5, 1]]);
+    }
+
+    #[test]
+    fn edge_weight_spares_the_inner_cells() {
+        // 3x3 de couleur 1, le coin (2, 2) en couleur 2 et le centre déjà pondéré
+        let pixels = (0..9)
+            .map(|i| PatternPixel { weight: (i == 4).then_some(7), ..pixel(i % 3, i / 3, if i == 8 { 2 } else { 1 }) })
+            .collect();
+        let weighted = edge_weight(&Pattern::new(3, 3, pixels), 5);
+        let mut weights = vec![vec![0; 3]; 3];
+        for p in weighted.pixels() {
+            weights[p.y as usize][p.x as usize] = p.weight();
+        }
+        assert_eq!(weights, vec![vec![5, 5, 5], vec![5, 7, 5], vec![5, 5, 5]]);
+
+        // Seul le centre d'un carré uni garde son poids
+        let plain = Pattern::new(3, 3, (0..9).map(|i| pixel(i % 3, i / 3, 1)).collect());
+        let inner: Vec<(i32, i32)> = edge_weight(&plain, 5).pixels().filter(|p| p.weight.is_none()).map(|p| (p.x, p.y)).collect();
+        assert_eq!(inner, vec![(1, 1)]);
+
+        assert!(check_edge_weight_output(Some(5), Path::new("out.rle")).is_err());
+        assert!(check_edge_weight_output(Some(5), Path::new("out.json")).is_ok());
+        assert!(check_edge_weight_output(None, Path::new("out.txt")).is_ok());
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{Result, anyhow};
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+use crate::args_parser::{ArgSpecs, ValidateArgs};
+use crate::ascii::{self, GridEncoding};
+use crate::board::Board;
+use crate::palette::Palette;
+use crate::pattern::{PatternFile, PixelColor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Le client tourne quand même, le pixel concerné est ignoré ou l'info est inutilisée
+    Warning,
+    /// Le pattern ne peut pas être placé correctement
+    Error,
+}
+
+/// Un problème dans un fichier pattern, avec sa position quand on la connaît
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub path: String,
+    pub position: Option<(usize, usize)>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{}:{}", line, column)?;
+        }
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, ": {}: {}", severity, self.message)
+    }
+}
+
+/// Ce contre quoi le pattern est vérifié
+pub struct CheckContext<'a> {
+    /// Ids des couleurs du serveur
+    pub colors: &'a HashSet<u8>,
+    /// Origine du pattern sur la board
+    pub origin: (i32, i32),
+    /// `(width, height)` de la board, quand on la connaît
+    pub board_size: Option<(usize, usize)>,
+}
+
+/// Ligne et colonne (à partir de 1) de l'octet `offset`
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |last| last.chars().count()) + 1;
+    (line, column)
+}
+
+/// Offset du début de chaque objet du tableau `"pattern"`, dans l'ordre du fichier
+fn pixel_offsets(content: &str) -> Vec<usize> {
+    let Some(key) = content.find("\"pattern\"") else {
+        return Vec::new();
+    };
+    let start = key + "\"pattern\"".len();
+    let mut offsets = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (offset, c) in content[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => {
+                if c == '{' && depth == 1 {
+                    offsets.push(start + offset);
+                }
+                depth += 1;
+            }
+            ']' | '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    offsets
+}
+
+/// Vérifie un fichier pattern et renvoie tous les problèmes trouvés
+pub fn check_pattern(path: &str, context: &CheckContext) -> Vec<Diagnostic> {
+    let diagnostic = |position, severity, message: String| Diagnostic {
+        path: path.to_string(),
+        position,
+        severity,
+        message,
+    };
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => return vec![diagnostic(None, Severity::Error, format!("couldn't read file: {}", e))],
+    };
+    // Fichier brut : les pixels hors de la boîte y sont encore, pour pouvoir les signaler
+    let (pattern, positions): (PatternFile, Vec<(usize, usize)>) = if let Some(encoding) = GridEncoding::from_path(Path::new(path)) {
+        match ascii::parse(&content, encoding) {
+            Ok(parsed) => parsed,
+            // Les erreurs du format texte donnent déjà leur ligne
+            Err(e) => return vec![diagnostic(None, Severity::Error, e.to_string())],
+        }
+    } else {
+        match serde_json::from_str::<PatternFile>(&content) {
+            Ok(pattern) => {
+                let positions = pixel_offsets(&content)
+                    .into_iter()
+                    .map(|offset| line_column(&content, offset))
+                    .collect();
+                (pattern, positions)
+            }
+            Err(e) => {
+                // serde_json donne déjà la ligne et la colonne, on les sort du message
+                let message = e.to_string();
+                let message = message.split(" at line ").next().unwrap_or(&message).to_string();
+                return vec![diagnostic(Some((e.line(), e.column())), Severity::Error, message)];
+            }
+        }
+    };
+
+    let mut diagnostics = Vec::new();
+    let position = |i: usize| {
+        // Si le repérage échoue (fichier inhabituel), on garde le message sans position
+        if positions.len() == pattern.pattern.len() {
+            Some(positions[i])
+        } else {
+            None
+        }
+    };
+
+    let (origin_x, origin_y) = context.origin;
+    let (box_width, box_height) = pattern.bounding_box();
+    let mut seen: HashMap<(i32, i32), (usize, &PixelColor)> = HashMap::new();
+    for (i, p) in pattern.pattern.iter().enumerate() {
+        for id in p.color.ids() {
+            if !context.colors.contains(id) {
+                diagnostics.push(diagnostic(position(i), Severity::Error,
+                    format!("pixel ({}, {}) uses color {} which is not in the palette", p.x, p.y, id)));
+            }
+        }
+
+        match seen.get(&(p.x, p.y)) {
+            Some(&(first, color)) => {
+                let first_at = position(first).map_or(String::new(), |(line, _)| format!(" at line {}", line));
+                if *color != p.color {
+                    diagnostics.push(diagnostic(position(i), Severity::Error,
+                        format!("pixel ({}, {}) has color {} but was already given color {}{}",
+                            p.x, p.y, p.color, color, first_at)));
+                } else {
+                    diagnostics.push(diagnostic(position(i), Severity::Warning,
+                        format!("pixel ({}, {}) is duplicated{}", p.x, p.y, first_at)));
+                }
+            }
+            None => {
+                seen.insert((p.x, p.y), (i, &p.color));
+            }
+        }
+
+        if !p.in_box(box_width, box_height) {
+            diagnostics.push(diagnostic(position(i), Severity::Warning,
+                format!("pixel ({}, {}) is outside the {}x{} pattern box and will be ignored",
+                    p.x, p.y, box_width, box_height)));
+            continue;
+        }
+        // En i64 : un pixel lointain et une origine non nulle ne doivent pas déborder
+        let (x, y) = (origin_x as i64 + p.x as i64, origin_y as i64 + p.y as i64);
+        if x < 0 || y < 0 {
+            diagnostics.push(diagnostic(position(i), Severity::Warning,
+                format!("pixel ({}, {}) lands on negative board coordinates ({}, {})", p.x, p.y, x, y)));
+        } else if let Some((width, height)) = context.board_size {
+            if x as usize >= width || y as usize >= height {
+                diagnostics.push(diagnostic(position(i), Severity::Warning,
+                    format!("pixel ({}, {}) lands at ({}, {}), outside of the {}x{} board",
+                        p.x, p.y, x, y, width, height)));
+            }
+        }
+    }
+
+    // Une boîte plus petite que les pixels est déjà signalée pixel par pixel, reste le cas d'une boîte trop grande
+    let (extent_x, extent_y) = pattern.extent();
+    for (key, declared, extent) in [("width", pattern.width, extent_x), ("height", pattern.height, extent_y)] {
+        if let Some(declared) = declared {
+            if declared > extent {
+                let position = content
+                    .find(&format!("\"{}\"", key))
+                    .map(|offset| line_column(&content, offset));
+                diagnostics.push(diagnostic(position, Severity::Warning,
+                    format!("{} is {} but the pixels only span {}", key, declared, extent)));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// Vérifie tous les patterns à leur origine ; les erreurs sont renvoyées, les warnings seulement affichés
+pub fn check_patterns(specs: &[ArgSpecs], colors: &HashSet<u8>, board_size: Option<(usize, usize)>) -> Result<()> {
+    let mut errors = Vec::new();
+    for spec in specs {
+        let context = CheckContext { colors, origin: (spec.x, spec.y), board_size };
+        for diagnostic in check_pattern(&spec.pattern_path, &context) {
+            match diagnostic.severity {
+                Severity::Warning => warn!("{}", diagnostic),
+                Severity::Error => errors.push(diagnostic.to_string()),
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(anyhow!("Invalid patterns:\n  - {}", errors.join("\n  - ")));
+    }
+    Ok(())
+}
+
+/// Sous-commande `validate` : les fichiers donnés, ou à défaut les patterns de la config à leur origine
+pub fn run(args: &ValidateArgs, specs: &[ArgSpecs], snapshot_dir: &Path) -> Result<()> {
+    let palette = Palette::resolve(args.palette.as_deref(), snapshot_dir)?;
+    let colors: HashSet<u8> = palette.ids().collect();
+
+    let targets: Vec<(String, CheckContext)> = if args.files.is_empty() {
+        let board_size = Board::load_snapshot(snapshot_dir)
+            .ok()
+            .map(|board| (board.width(), board.height()));
+        specs
+            .iter()
+            .map(|spec| {
+                // L'origine "auto" n'est connue qu'une fois la board récupérée
+                let board_size = board_size.filter(|_| !spec.auto_origin);
+                (spec.pattern_path.clone(), CheckContext { colors: &colors, origin: (spec.x, spec.y), board_size })
+            })
+            .collect()
+    } else {
+        args.files
+            .iter()
+            .map(|path| (path.to_string_lossy().into_owned(), CheckContext { colors: &colors, origin: (0, 0), board_size: None }))
+            .collect()
+    };
+
+    let (mut errors, mut warnings) = (0, 0);
+    for (path, context) in &targets {
+        for diagnostic in check_pattern(path, context) {
+            match diagnostic.severity {
+                Severity::Warning => warnings += 1,
+                Severity::Error => errors += 1,
+            }
+            println!("{}", diagnostic);
+        }
+    }
+    println!("{} files checked: {} errors, {} warnings", targets.len(), errors, warnings);
+
+    if errors > 0 {
+        return Err(anyhow!("{} invalid pattern errors", errors));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Found = (Severity, Option<(usize, usize)>, String);
+
+    /// Écrit `content` dans un fichier temporaire et le vérifie avec les couleurs 1, 2 et 3
+    fn check(file_name: &str, content: &str, origin: (i32, i32), board_size: Option<(usize, usize)>) -> Vec<Found> {
+        let dir = std::env::temp_dir().join(format!("validate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file_name);
+        fs::write(&path, content).unwrap();
+        let colors = HashSet::from([1, 2, 3]);
+        let context = CheckContext { colors: &colors, origin, board_size };
+        check_pattern(&path.to_string_lossy(), &context)
+            .into_iter()
+            .map(|d| (d.severity, d.position, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn json_duplicates_colors_and_box() {
+        let content = r#"{
+  "width": 4,
+  "height": 3,
+  "pattern": [
+    {"x": 0, "y": 0, "color": 1},
+    {"x": 1, "y": 0, "color": 2},
+    {"x": 0, "y": 0, "color": 1},
+    {"x": 1, "y": 0, "color": 3},
+    {"x": 0, "y": 1, "color": 99}
+  ]
+}
+"#;
+        assert_eq!(check("diagnostics.json", content, (0, 0), None), vec![
+            (Severity::Warning, Some((7, 5)), "pixel (0, 0) is duplicated at line 5".to_string()),
+            (Severity::Error, Some((8, 5)), "pixel (1, 0) has color 3 but was already given color 2 at line 6".to_string()),
+            (Severity::Error, Some((9, 5)), "pixel (0, 1) uses color 99 which is not in the palette".to_string()),
+            (Severity::Warning, Some((2, 3)), "width is 4 but the pixels only span 2".to_string()),
+            (Severity::Warning, Some((3, 3)), "height is 3 but the pixels only span 2".to_string()),
+        ]);
+
+        let broken = check("broken.json", "{\n  \"pattern\": [\n    {\"x\": 0,}\n  ]\n}\n", (0, 0), None);
+        assert_eq!(broken.len(), 1);
+        assert_eq!((broken[0].0, broken[0].1), (Severity::Error, Some((3, 13))));
+    }
+
+    #[test]
+    fn text_pixels_outside_the_board() {
+        let content = "legend:\nr = 1\ngrid:\nr..\n..r\n";
+        assert_eq!(check("board.txt", content, (-1, 1), Some((2, 2))), vec![
+            (Severity::Warning, Some((4, 1)), "pixel (0, 0) lands on negative board coordinates (-1, 1)".to_string()),
+            (Severity::Warning, Some((5, 3)), "pixel (2, 1) lands at (1, 2), outside of the 2x2 board".to_string()),
+        ]);
+        assert!(check("board.txt", content, (0, 0), Some((3, 2))).is_empty());
+    }
+
+    #[test]
+    fn pixel_offsets_point_at_each_pixel_object() {
+        let content = "{\n  \"width\": 2,\n  \"pattern\": [\n    {\"x\": 0, \"y\": 0, \"color\": 1},\n    {\"x\": 1, \"y\": 0, \"color\": 2}\n  ]\n}\n";
+        let positions: Vec<_> = pixel_offsets(content)
+            .into_iter()
+            .map(|offset| line_column(content, offset))
+            .collect();
+        assert_eq!(positions, vec![(4, 5), (5, 5)]);
+    }
+}